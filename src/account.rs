@@ -1,48 +1,221 @@
 use crate::client::{HttpClient, Response};
 use crate::directory::Directory;
-use crate::ecdsa::{generate_pkcs8_ecdsa_keypair, keypair_from_pkcs8};
-use crate::errors::{Error, ErrorKind, Result};
-use crate::jose::jose;
-use ring::signature::EcdsaKeyPair;
+use crate::ecdsa::generate_pkcs8_ecdsa_keypair;
+use crate::errors::{Error, ErrorKind, ProblemType, Result};
+use crate::jose::{eab_jose, jose};
+use crate::key::{AccountKey, KeyAlgorithm, Signer, pkcs8_from_jwk, pkcs8_from_pem};
+use crate::order::{AccountOrder, list_orders};
+use ::base64::Engine;
+use ::base64::prelude::BASE64_URL_SAFE_NO_PAD;
 use serde::{Deserialize, Serialize};
-use serde_json::json;
+use serde_json::{Value, json};
 use std::fmt::{Display, Formatter};
-#[cfg(feature = "tracing")]
-use tracing::debug;
 
 /// Because we have only have an easy way to go from pkcs8 to keypair,
 /// but not the other way around, we store the keypair in both its
-/// EcdsaKeyPair deserialized version, and its PKCS8 serialized version.
+/// deserialized version, and its PKCS8 serialized version.
+/// The key can be ECDSA P-256 or P-384, RSA or Ed25519.
+///
+/// The key can also be held outside the process by a [Signer]
+/// (see [AccountMaterial::from_signer]). There is no PKCS8 version in that case,
+/// and only the account url is serialized
+/// (see [AccountMaterial::from_json_with_signer]).
 #[derive(Serialize)]
 pub struct AccountMaterial {
     #[serde(skip_serializing)]
-    pub(crate) keypair: EcdsaKeyPair,
-    #[serde(with = "base64")]
+    pub(crate) keypair: Box<dyn Signer>,
+    #[serde(with = "base64", skip_serializing_if = "Vec::is_empty")]
     pkcs8: Vec<u8>,
     /// the account url is also referred to as `kid` in the RFC.
     pub(crate) url: String,
 }
 
+/// [RFC 8555 External Account Binding](https://datatracker.ietf.org/doc/html/rfc8555#section-7.3.4)
+/// The key identifier and MAC key provided by the CA to bind the new ACME account
+/// to an existing account in its non-ACME system.
+#[derive(Clone)]
+pub struct ExternalAccountBinding {
+    key_id: String,
+    hmac_key: Vec<u8>,
+}
+
+impl ExternalAccountBinding {
+    pub fn new(key_id: impl Into<String>, hmac_key: impl Into<Vec<u8>>) -> Self {
+        Self {
+            key_id: key_id.into(),
+            hmac_key: hmac_key.into(),
+        }
+    }
+    /// CAs usually provide the MAC key base64url encoded.
+    pub fn from_base64(key_id: impl Into<String>, hmac_key: impl AsRef<str>) -> Result<Self> {
+        let hmac_key = BASE64_URL_SAFE_NO_PAD
+            .decode(hmac_key.as_ref().trim_end_matches('='))
+            .map_err(|_| ErrorKind::InvalidExternalAccountKey)?;
+        Ok(Self::new(key_id, hmac_key))
+    }
+}
+
+/// [RFC 8555 Account contacts](https://datatracker.ietf.org/doc/html/rfc8555#section-7.3)
+/// The contact URIs of an account (there can be none).
+/// Email addresses without a scheme are converted to `mailto:` URIs.
+/// Other URI schemes are only accepted by some ACME servers.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Contacts(Vec<String>);
+
+impl Contacts {
+    /// No contact, the ACME server won't be able to reach the account owner.
+    pub fn none() -> Self {
+        Self::default()
+    }
+    /// Email addresses (with or without `mailto:`) or other contact URIs.
+    pub fn new(contacts: impl IntoIterator<Item = impl AsRef<str>>) -> Self {
+        Self(
+            contacts
+                .into_iter()
+                .map(|it| {
+                    let contact = it.as_ref().trim();
+                    match scheme(contact) {
+                        Some(_) => contact.to_string(),
+                        None => format!("mailto:{contact}"),
+                    }
+                })
+                .collect(),
+        )
+    }
+    /// The contact URIs, as sent to the ACME server.
+    pub fn uris(&self) -> &[String] {
+        &self.0
+    }
+    /// Check that the contacts are URIs, and that the `mailto:` URIs are
+    /// a single email address without header fields, as required by
+    /// [RFC 8555](https://datatracker.ietf.org/doc/html/rfc8555#section-7.3).
+    pub fn validate(&self) -> Result<()> {
+        for contact in &self.0 {
+            let invalid = || ErrorKind::InvalidContact {
+                contact: contact.clone(),
+            };
+            if contact
+                .chars()
+                .any(|it| it.is_whitespace() || it.is_control())
+            {
+                return Err(invalid().into());
+            }
+            let (scheme, value) = scheme(contact).ok_or_else(invalid)?;
+            if value.is_empty() {
+                return Err(invalid().into());
+            }
+            if scheme.eq_ignore_ascii_case("mailto") {
+                if value.contains(['?', ',']) {
+                    return Err(invalid().into());
+                }
+                match value.split_once('@') {
+                    Some((local, domain))
+                        if !local.is_empty()
+                            && !domain.is_empty()
+                            && !domain.contains('@')
+                            && !domain.starts_with('.')
+                            && !domain.ends_with('.') => {}
+                    _ => return Err(invalid().into()),
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// [RFC 3986 Scheme](https://datatracker.ietf.org/doc/html/rfc3986#section-3.1)
+/// Split a URI into its scheme and the rest.
+fn scheme(uri: &str) -> Option<(&str, &str)> {
+    let (scheme, value) = uri.split_once(':')?;
+    let mut chars = scheme.chars();
+    match chars.next() {
+        Some(first)
+            if first.is_ascii_alphabetic()
+                && chars.all(|it| it.is_ascii_alphanumeric() || "+-.".contains(it)) =>
+        {
+            Some((scheme, value))
+        }
+        _ => None,
+    }
+}
+
+impl From<&str> for Contacts {
+    fn from(value: &str) -> Self {
+        Self::new([value])
+    }
+}
+
+impl From<String> for Contacts {
+    fn from(value: String) -> Self {
+        Self::new([value])
+    }
+}
+
+impl From<&String> for Contacts {
+    fn from(value: &String) -> Self {
+        Self::new([value])
+    }
+}
+
+impl<S: AsRef<str>> From<Vec<S>> for Contacts {
+    fn from(value: Vec<S>) -> Self {
+        Self::new(value)
+    }
+}
+
+impl<S: AsRef<str>> From<&[S]> for Contacts {
+    fn from(value: &[S]) -> Self {
+        Self::new(value)
+    }
+}
+
+impl<S: AsRef<str>, const N: usize> From<[S; N]> for Contacts {
+    fn from(value: [S; N]) -> Self {
+        Self::new(value)
+    }
+}
+
 #[derive(Deserialize)]
 struct PackedAccountMaterial {
-    #[serde(with = "base64")]
+    /// Missing for accounts with a key held by a [Signer].
+    #[serde(default, with = "base64")]
     pkcs8: Vec<u8>,
     url: String,
 }
 
 /// [RFC 8555 Account](https://datatracker.ietf.org/doc/html/rfc8555#section-7.1.2)
-/// We only use the status field.
-#[derive(Deserialize, Debug)]
-pub(crate) struct Account {
-    // contact: Vec<String>,
+#[derive(Deserialize, Debug, Clone)]
+pub struct Account {
     #[serde(flatten)]
-    status: AccountStatus,
+    pub status: AccountStatus,
+    /// The contact URIs (e.g. `mailto:admin@example.org`).
+    #[serde(default)]
+    pub contact: Vec<String>,
+    /// Not all ACME servers return it.
+    #[serde(rename = "termsOfServiceAgreed")]
+    pub terms_of_service_agreed: Option<bool>,
+    /// The orders list url. Let's Encrypt doesn't provide the orders list.
+    pub orders: Option<String>,
+    /// The external account binding JWS, for accounts created with one.
+    #[serde(rename = "externalAccountBinding")]
+    pub external_account_binding: Option<Value>,
+}
+
+impl Account {
+    /// The key identifier of the external account that the account is bound to,
+    /// from the protected header of the external account binding.
+    pub fn external_account_key_id(&self) -> Option<String> {
+        let protected = self.external_account_binding.as_ref()?["protected"].as_str()?;
+        let protected = BASE64_URL_SAFE_NO_PAD.decode(protected).ok()?;
+        let protected = serde_json::from_slice::<Value>(&protected).ok()?;
+        protected["kid"].as_str().map(ToString::to_string)
+    }
 }
 
 /// [RFC 8555 Account State](https://datatracker.ietf.org/doc/html/rfc8555#page-33)
-#[derive(Deserialize, Debug, PartialEq, Eq)]
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
 #[serde(tag = "status")]
-pub(crate) enum AccountStatus {
+pub enum AccountStatus {
     #[serde(rename = "valid")]
     Valid,
     #[serde(rename = "deactivated")]
@@ -61,11 +234,28 @@ impl Display for AccountStatus {
     }
 }
 
+/// The registration resource saved by certbot (`regr.json`) and lego (in `account.json`).
+#[derive(Deserialize)]
+struct RegistrationResource {
+    uri: String,
+}
+
+/// The account saved by lego (`account.json`).
+#[derive(Deserialize)]
+struct LegoAccount {
+    registration: Option<RegistrationResource>,
+}
+
 impl TryFrom<PackedAccountMaterial> for AccountMaterial {
     type Error = Error;
     fn try_from(value: PackedAccountMaterial) -> Result<Self> {
+        if value.pkcs8.is_empty() {
+            return Err(ErrorKind::DeserializeAccount.with_msg(
+                "the account key is held by a signer (see AccountMaterial::from_json_with_signer)",
+            ));
+        }
         Ok(Self {
-            keypair: keypair_from_pkcs8(&value.pkcs8)?,
+            keypair: Box::new(AccountKey::from_pkcs8(&value.pkcs8)?),
             pkcs8: value.pkcs8,
             url: value.url,
         })
@@ -91,116 +281,347 @@ impl AccountMaterial {
     pub fn to_json(&self) -> String {
         serde_json::to_string(self).expect("failed to serialize account material")
     }
+    /// The signature algorithm of the account key.
+    pub fn algorithm(&self) -> KeyAlgorithm {
+        self.keypair.algorithm()
+    }
     /// Deserialize from json and check with the acme server that the account status is valid.
-    /// If the account is invalid, it might be because the terms of service need to be agreed to,
-    /// in which case, update the account with the terms of service agreement.
-    /// If the account is not found, then create a new one.
+    /// The account contacts are replaced with the specified ones if they are different,
+    /// but they are kept if none are specified (see [AccountMaterial::update_contact]
+    /// to remove them).
+    /// If the account is not found, then create a new one, unless that requires agreeing
+    /// to the terms of service of the ACME server.
+    /// If the ACME server requires agreeing to updated terms of service,
+    /// this fails with [ErrorKind::UserActionRequired]
+    /// (see [AccountMaterial::from_json_with_agreement]).
+    pub async fn from_json<C: HttpClient<R>, R: Response>(
+        json: impl AsRef<str>,
+        contacts: impl Into<Contacts>,
+        directory: &Directory,
+        client: &C,
+    ) -> Result<AccountMaterial> {
+        Self::restore(
+            Self::unpack(json)?,
+            contacts.into(),
+            None,
+            None,
+            directory,
+            client,
+        )
+        .await
+    }
+    /// Same as [AccountMaterial::from_json], but if a new account needs to be created,
+    /// it is bound to the specified external account.
+    pub async fn from_json_with_eab<C: HttpClient<R>, R: Response>(
+        json: impl AsRef<str>,
+        contacts: impl Into<Contacts>,
+        eab: &ExternalAccountBinding,
+        directory: &Directory,
+        client: &C,
+    ) -> Result<AccountMaterial> {
+        Self::restore(
+            Self::unpack(json)?,
+            contacts.into(),
+            Some(eab),
+            None,
+            directory,
+            client,
+        )
+        .await
+    }
+    /// Same as [AccountMaterial::from_json], but if the ACME server requires agreeing
+    /// to updated terms of service, or if the account needs to be created again,
+    /// the agreement callback is called with the url of the terms,
+    /// and they are agreed to only if it returns true.
+    pub async fn from_json_with_agreement<C: HttpClient<R>, R: Response>(
+        json: impl AsRef<str>,
+        contacts: impl Into<Contacts>,
+        eab: Option<&ExternalAccountBinding>,
+        agreement: impl Fn(&str) -> bool,
+        directory: &Directory,
+        client: &C,
+    ) -> Result<AccountMaterial> {
+        Self::restore(
+            Self::unpack(json)?,
+            contacts.into(),
+            eab,
+            Some(&agreement),
+            directory,
+            client,
+        )
+        .await
+    }
+    /// Same as [AccountMaterial::from_json], for an account with a key held by a [Signer]
+    /// (see [AccountMaterial::from_signer]). Only the account url is read from the json.
+    pub async fn from_json_with_signer<C: HttpClient<R>, R: Response>(
+        json: impl AsRef<str>,
+        signer: impl Signer + 'static,
+        contacts: impl Into<Contacts>,
+        eab: Option<&ExternalAccountBinding>,
+        directory: &Directory,
+        client: &C,
+    ) -> Result<AccountMaterial> {
+        let url = serde_json::from_str::<PackedAccountMaterial>(json.as_ref())
+            .map_err(|_| ErrorKind::DeserializeAccount.with_msg("invalid account json"))?
+            .url;
+        let account = AccountMaterial {
+            keypair: Box::new(signer),
+            pkcs8: Vec::new(),
+            url,
+        };
+        Self::restore(account, contacts.into(), eab, None, directory, client).await
+    }
+    fn unpack(json: impl AsRef<str>) -> Result<AccountMaterial> {
+        serde_json::from_str::<PackedAccountMaterial>(json.as_ref())
+            .map_err(|_| ErrorKind::DeserializeAccount.into())
+            .and_then(|it| it.try_into())
+    }
     #[cfg_attr(feature = "tracing", tracing::instrument(
         name = "get_account_from_json",
         skip_all,
         level = tracing::Level::DEBUG,
         err(level = tracing::Level::WARN)
     ))]
-    pub async fn from_json<C: HttpClient<R>, R: Response>(
-        json: impl AsRef<str>,
-        contact_email: impl AsRef<str>,
+    async fn restore<C: HttpClient<R>, R: Response>(
+        account: AccountMaterial,
+        contacts: Contacts,
+        eab: Option<&ExternalAccountBinding>,
+        agreement: Option<&dyn Fn(&str) -> bool>,
         directory: &Directory,
         client: &C,
     ) -> Result<AccountMaterial> {
-        // Restore account material from json
-        let account: AccountMaterial = serde_json::from_str::<PackedAccountMaterial>(json.as_ref())
-            .map_err(|_| ErrorKind::DeserializeAccount.into())
-            .and_then(|it| it.try_into())?;
         // Get the existing account if it exists
         // [rfc8555#section-7.3.1](https://datatracker.ietf.org/doc/html/rfc8555#section-7.3.1)
-        let nonce = directory.new_nonce(client).await?;
         let payload = json!({
             "onlyReturnExisting": true
         });
-        let body = jose(
-            &account.keypair,
-            Some(payload),
-            Some(&account.url),
-            Some(&nonce),
-            &account.url,
-        );
-        let response = client
-            .post_jose(&account.url, &body)
-            .await
-            .map_err(|err| ErrorKind::GetAccount.wrap(err))?;
-        match response.status_code() {
-            200 => {
+        let result = directory
+            .signed_request(
+                &account.url,
+                async |nonce| {
+                    jose(
+                        &account.keypair,
+                        Some(payload.clone()),
+                        Some(&account.url),
+                        Some(nonce),
+                        &account.url,
+                    )
+                    .await
+                },
+                client,
+            )
+            .await;
+        match result {
+            Ok(response) => {
                 // Account found, check that its status is valid.
-                let status = response
+                let registered = response
                     .body_as_json::<Account>()
                     .await
-                    .map_err(|err| ErrorKind::GetAccount.wrap(err))?
-                    .status;
-                match status {
+                    .map_err(|err| ErrorKind::GetAccount.wrap(err))?;
+                match registered.status {
                     AccountStatus::Valid => {
                         account
-                            .update_contact(contact_email, directory, client)
+                            .replace_contact(&contacts, &registered.contact, directory, client)
                             .await?;
                         Ok(account)
                     }
-                    _ => Err(ErrorKind::GetAccount.with_msg(format!("account is {status}"))),
+                    status => Err(ErrorKind::GetAccount.with_msg(format!("account is {status}"))),
                 }
             }
-            403 => {
-                // Try to update with the terms of service agreement.
-                #[cfg(feature = "tracing")]
-                if let Ok(text) = response.body_as_text().await {
-                    debug!(body = ?text)
+            Err(err) => match err.problem() {
+                // [RFC 8555 Changes of Terms of Service](https://datatracker.ietf.org/doc/html/rfc8555#section-7.3.3)
+                Some(problem) if problem.kind == ProblemType::UserActionRequired => {
+                    let terms_of_service = problem.terms_of_service.clone();
+                    match (&terms_of_service, agreement) {
+                        (Some(terms_of_service), Some(agree)) if agree(terms_of_service) => {
+                            account.agree_to_terms_of_service(directory, client).await?;
+                            if !contacts.uris().is_empty() {
+                                let registered = account.account(directory, client).await?;
+                                account
+                                    .replace_contact(
+                                        &contacts,
+                                        &registered.contact,
+                                        directory,
+                                        client,
+                                    )
+                                    .await?;
+                            }
+                            Ok(account)
+                        }
+                        // The problem instance url, in the cause, explains what needs to be done.
+                        _ => Err(ErrorKind::UserActionRequired { terms_of_service }.wrap(err)),
+                    }
                 }
-                #[cfg(not(feature = "tracing"))]
-                let _ = response.body_as_text();
-                account
-                    .update_contact(contact_email, directory, client)
-                    .await?;
-                Ok(account)
-            }
-            400 | 404 => {
-                // Account not found, create a new one.
-                Self::new_account(
-                    account.pkcs8,
-                    account.keypair,
-                    contact_email,
-                    directory,
-                    client,
-                )
-                .await
-            }
-            _ => {
-                #[cfg(feature = "tracing")]
-                if let Ok(text) = response.body_as_text().await {
-                    debug!(body = ?text)
+                problem
+                    if matches!(err.kind, ErrorKind::Rejected { status: 404 })
+                        || problem
+                            .is_some_and(|it| it.kind == ProblemType::AccountDoesNotExist) =>
+                {
+                    // Account not found, create a new one. This agrees to the terms of service,
+                    // which can only be done with the consent of the caller.
+                    if let Some(terms_of_service) = &directory.meta().terms_of_service
+                        && !agreement.is_some_and(|agree| agree(terms_of_service))
+                    {
+                        return Err(ErrorKind::UserActionRequired {
+                            terms_of_service: Some(terms_of_service.clone()),
+                        }
+                        .wrap(err));
+                    }
+                    Self::new_account(
+                        account.pkcs8,
+                        account.keypair,
+                        contacts,
+                        eab,
+                        directory,
+                        client,
+                    )
+                    .await
                 }
-                #[cfg(not(feature = "tracing"))]
-                let _ = response.body_as_text();
-                Err(ErrorKind::GetAccount.into())
-            }
+                _ => Err(ErrorKind::GetAccount.wrap(err)),
+            },
         }
     }
+    /// Import a certbot account, from the `private_key.json` and `regr.json` files in
+    /// `accounts/<acme server>/directory/<account id>/`,
+    /// and check with the acme server that the account exists and that its status is valid.
+    /// The account is never created, and its contacts are left unchanged.
+    pub async fn from_certbot<C: HttpClient<R>, R: Response>(
+        private_key_json: impl AsRef<str>,
+        regr_json: impl AsRef<str>,
+        directory: &Directory,
+        client: &C,
+    ) -> Result<AccountMaterial> {
+        let registration = serde_json::from_str::<RegistrationResource>(regr_json.as_ref())
+            .map_err(|_| ErrorKind::ImportAccount.with_msg("invalid certbot regr.json"))?;
+        let pkcs8 = pkcs8_from_jwk(private_key_json.as_ref())
+            .map_err(|err| ErrorKind::ImportAccount.wrap(err))?;
+        Self::import(pkcs8, registration.uri, directory, client).await
+    }
+    /// Import a lego account, from the `keys/<email>.key` and `account.json` files in
+    /// `accounts/<acme server>/<email>/`,
+    /// and check with the acme server that the account exists and that its status is valid.
+    /// The account is never created, and its contacts are left unchanged.
+    pub async fn from_lego<C: HttpClient<R>, R: Response>(
+        key_pem: impl AsRef<[u8]>,
+        account_json: impl AsRef<str>,
+        directory: &Directory,
+        client: &C,
+    ) -> Result<AccountMaterial> {
+        let registration = serde_json::from_str::<LegoAccount>(account_json.as_ref())
+            .map_err(|_| ErrorKind::ImportAccount.with_msg("invalid lego account.json"))?
+            .registration
+            .ok_or_else(|| {
+                ErrorKind::ImportAccount.with_msg("the lego account is not registered")
+            })?;
+        let pkcs8 =
+            pkcs8_from_pem(key_pem.as_ref()).map_err(|err| ErrorKind::ImportAccount.wrap(err))?;
+        Self::import(pkcs8, registration.uri, directory, client).await
+    }
+    #[cfg_attr(feature = "tracing", tracing::instrument(
+        name = "import_account",
+        skip(pkcs8, directory, client),
+        level = tracing::Level::DEBUG,
+        err(level = tracing::Level::WARN)
+    ))]
+    async fn import<C: HttpClient<R>, R: Response>(
+        pkcs8: Vec<u8>,
+        url: String,
+        directory: &Directory,
+        client: &C,
+    ) -> Result<AccountMaterial> {
+        let keypair =
+            AccountKey::from_pkcs8(&pkcs8).map_err(|err| ErrorKind::ImportAccount.wrap(err))?;
+        // [RFC 8555 Finding an Account URL Given a Key](https://datatracker.ietf.org/doc/html/rfc8555#section-7.3.1)
+        let payload = json!({
+            "onlyReturnExisting": true
+        });
+        let response = directory
+            .signed_request(
+                &directory.new_account,
+                async |nonce| {
+                    jose(
+                        &keypair,
+                        Some(payload.clone()),
+                        None,
+                        Some(nonce),
+                        &directory.new_account,
+                    )
+                    .await
+                },
+                client,
+            )
+            .await
+            .map_err(|err| ErrorKind::ImportAccount.wrap(err))?;
+        // The account url returned by the acme server takes precedence over the saved one,
+        // which can be outdated (e.g. an ACME v1 registration url).
+        let url = response.header_value("location").unwrap_or(url);
+        let account = response
+            .body_as_json::<Account>()
+            .await
+            .map_err(|err| ErrorKind::ImportAccount.wrap(err))?;
+        match account.status {
+            AccountStatus::Valid => Ok(AccountMaterial {
+                keypair: Box::new(keypair),
+                pkcs8,
+                url,
+            }),
+            _ => Err(ErrorKind::ImportAccount.with_msg(format!("account is {}", account.status))),
+        }
+    }
+    /// Create a new account with an existing key
+    /// (ECDSA P-256 or P-384, RSA or Ed25519, see [crate::key::generate_pkcs8_keypair]).
     pub async fn from_pkcs8<C: HttpClient<R>, R: Response>(
         pkcs8: Vec<u8>,
-        contact_email: impl AsRef<str>,
+        contacts: impl Into<Contacts>,
         directory: &Directory,
         client: &C,
     ) -> Result<AccountMaterial> {
-        let keypair = keypair_from_pkcs8(&pkcs8)?;
-        Self::new_account(pkcs8, keypair, contact_email, directory, client).await
+        let keypair = Box::new(AccountKey::from_pkcs8(&pkcs8)?);
+        Self::new_account(pkcs8, keypair, contacts.into(), None, directory, client).await
+    }
+    /// Create a new account bound to the specified external account.
+    pub async fn from_pkcs8_with_eab<C: HttpClient<R>, R: Response>(
+        pkcs8: Vec<u8>,
+        contacts: impl Into<Contacts>,
+        eab: &ExternalAccountBinding,
+        directory: &Directory,
+        client: &C,
+    ) -> Result<AccountMaterial> {
+        let keypair = Box::new(AccountKey::from_pkcs8(&pkcs8)?);
+        Self::new_account(
+            pkcs8,
+            keypair,
+            contacts.into(),
+            Some(eab),
+            directory,
+            client,
+        )
+        .await
+    }
+    /// Create a new account (or get the existing one) for a key held by a [Signer].
+    pub async fn from_signer<C: HttpClient<R>, R: Response>(
+        signer: impl Signer + 'static,
+        contacts: impl Into<Contacts>,
+        eab: Option<&ExternalAccountBinding>,
+        directory: &Directory,
+        client: &C,
+    ) -> Result<AccountMaterial> {
+        let keypair = Box::new(signer);
+        Self::new_account(Vec::new(), keypair, contacts.into(), eab, directory, client).await
     }
     pub(crate) async fn from<C: HttpClient<R>, R: Response>(
-        contact_email: impl AsRef<str>,
+        contacts: impl Into<Contacts>,
+        eab: Option<&ExternalAccountBinding>,
         directory: &Directory,
         client: &C,
     ) -> Result<Self> {
         let pkcs8 = generate_pkcs8_ecdsa_keypair();
-        let keypair = keypair_from_pkcs8(&pkcs8).expect("failed to extract keypair");
-        Self::new_account(pkcs8, keypair, contact_email, directory, client).await
+        let keypair = Box::new(AccountKey::from_pkcs8(&pkcs8).expect("failed to extract keypair"));
+        Self::new_account(pkcs8, keypair, contacts.into(), eab, directory, client).await
     }
 
     /// [RFC8555 Account Update](https://datatracker.ietf.org/doc/html/rfc8555#section-7.3.2)
+    /// Replace the account contacts (no contacts removes them all).
     #[cfg_attr(feature = "tracing", tracing::instrument(
         name = "update_account_contact",
         skip_all,
@@ -209,103 +630,277 @@ impl AccountMaterial {
     ))]
     pub async fn update_contact<C: HttpClient<R>, R: Response>(
         &self,
-        contact_email: impl AsRef<str>,
+        contacts: impl Into<Contacts>,
         directory: &Directory,
         client: &C,
     ) -> Result<()> {
-        let nonce = directory.new_nonce(client).await?;
+        let contacts = contacts.into();
+        contacts.validate()?;
         let payload = json!({
-            "termsOfServiceAgreed": true,
-            "contact": vec![format!("mailto:{}", contact_email.as_ref())]
+            "contact": contacts.uris()
         });
-        let body = jose(
-            &self.keypair,
-            Some(payload),
-            Some(&self.url),
-            Some(&nonce),
-            &self.url,
-        );
-        let response = client
-            .post_jose(&self.url, &body)
+        let response = directory
+            .signed_request(
+                &self.url,
+                async |nonce| {
+                    jose(
+                        &self.keypair,
+                        Some(payload.clone()),
+                        Some(&self.url),
+                        Some(nonce),
+                        &self.url,
+                    )
+                    .await
+                },
+                client,
+            )
             .await
             .map_err(|err| ErrorKind::GetAccount.wrap(err))?;
-        if response.is_success() {
-            let status = response
-                .body_as_json::<Account>()
-                .await
-                .map_err(|err| ErrorKind::NewAccount.wrap(err))?
-                .status;
-            match status {
-                AccountStatus::Valid => Ok(()),
-                _ => Err(ErrorKind::GetAccount.with_msg(format!("account is {status}"))),
-            }
-        } else {
-            #[cfg(feature = "tracing")]
-            if let Ok(text) = response.body_as_text().await {
-                debug!(body = ?text)
-            }
-            #[cfg(not(feature = "tracing"))]
-            let _ = response.body_as_text();
-            Err(ErrorKind::GetAccount.into())
+        let status = response
+            .body_as_json::<Account>()
+            .await
+            .map_err(|err| ErrorKind::NewAccount.wrap(err))?
+            .status;
+        match status {
+            AccountStatus::Valid => Ok(()),
+            _ => Err(ErrorKind::GetAccount.with_msg(format!("account is {status}"))),
         }
     }
-    /// [RFC8555 Account Key Rollover](https://datatracker.ietf.org/doc/html/rfc8555#section-7.3.5)
+    /// Replace the registered contacts with the specified ones,
+    /// unless none are specified or they are already registered.
+    async fn replace_contact<C: HttpClient<R>, R: Response>(
+        &self,
+        contacts: &Contacts,
+        registered: &[String],
+        directory: &Directory,
+        client: &C,
+    ) -> Result<()> {
+        if contacts.uris().is_empty() || contacts.uris() == registered {
+            return Ok(());
+        }
+        self.update_contact(contacts.clone(), directory, client)
+            .await
+    }
+    /// [RFC 8555 Changes of Terms of Service](https://datatracker.ietf.org/doc/html/rfc8555#section-7.3.3)
+    /// Agree to the current terms of service.
+    /// This should only be done after the new terms
+    /// (see [Problem::terms_of_service](crate::errors::Problem::terms_of_service))
+    /// have been approved.
     #[cfg_attr(feature = "tracing", tracing::instrument(
-        name = "update_account_key",
+        name = "agree_to_terms_of_service",
         skip_all,
         level = tracing::Level::DEBUG,
         err(level = tracing::Level::WARN)
     ))]
+    pub async fn agree_to_terms_of_service<C: HttpClient<R>, R: Response>(
+        &self,
+        directory: &Directory,
+        client: &C,
+    ) -> Result<()> {
+        let payload = json!({
+            "termsOfServiceAgreed": true
+        });
+        directory
+            .signed_request(
+                &self.url,
+                async |nonce| {
+                    jose(
+                        &self.keypair,
+                        Some(payload.clone()),
+                        Some(&self.url),
+                        Some(nonce),
+                        &self.url,
+                    )
+                    .await
+                },
+                client,
+            )
+            .await
+            .map_err(|err| ErrorKind::TermsOfServiceAgreement.wrap(err))?;
+        Ok(())
+    }
+    /// [RFC8555 Account Key Rollover](https://datatracker.ietf.org/doc/html/rfc8555#section-7.3.5)
+    /// Roll over to a new generated P-256 key.
     pub async fn update_key<C: HttpClient<R>, R: Response>(
         &self,
         directory: &Directory,
         client: &C,
     ) -> Result<Self> {
         let pkcs8 = generate_pkcs8_ecdsa_keypair();
-        let keypair = keypair_from_pkcs8(&pkcs8).expect("failed to extract keypair");
-        let nonce = directory.new_nonce(client).await?;
+        let keypair = Box::new(AccountKey::from_pkcs8(&pkcs8).expect("failed to extract keypair"));
+        self.change_key(pkcs8, keypair, directory, client).await
+    }
+    /// [RFC8555 Account Key Rollover](https://datatracker.ietf.org/doc/html/rfc8555#section-7.3.5)
+    /// Roll over to an existing key
+    /// (ECDSA P-256 or P-384, RSA or Ed25519, see [crate::key::generate_pkcs8_keypair]).
+    pub async fn update_key_with_pkcs8<C: HttpClient<R>, R: Response>(
+        &self,
+        pkcs8: Vec<u8>,
+        directory: &Directory,
+        client: &C,
+    ) -> Result<Self> {
+        let keypair = Box::new(
+            AccountKey::from_pkcs8(&pkcs8).map_err(|err| ErrorKind::ChangeAccountKey.wrap(err))?,
+        );
+        self.change_key(pkcs8, keypair, directory, client).await
+    }
+    /// [RFC8555 Account Key Rollover](https://datatracker.ietf.org/doc/html/rfc8555#section-7.3.5)
+    /// Roll over to a key held by a [Signer].
+    pub async fn update_key_with_signer<C: HttpClient<R>, R: Response>(
+        &self,
+        signer: impl Signer + 'static,
+        directory: &Directory,
+        client: &C,
+    ) -> Result<Self> {
+        self.change_key(Vec::new(), Box::new(signer), directory, client)
+            .await
+    }
+    #[cfg_attr(feature = "tracing", tracing::instrument(
+        name = "update_account_key",
+        skip_all,
+        level = tracing::Level::DEBUG,
+        err(level = tracing::Level::WARN)
+    ))]
+    async fn change_key<C: HttpClient<R>, R: Response>(
+        &self,
+        pkcs8: Vec<u8>,
+        keypair: Box<dyn Signer>,
+        directory: &Directory,
+        client: &C,
+    ) -> Result<Self> {
+        if keypair.jwk() == self.keypair.jwk() {
+            return Err(ErrorKind::ChangeAccountKey.with_msg("the new key is the current key"));
+        }
         let payload = json!({
             "account": &self.url,
-            "oldKey": crate::jose::jwk(&self.keypair)
+            "oldKey": self.keypair.jwk()
         });
-        let payload = jose(&keypair, Some(payload), None, None, &directory.key_change);
-        let body = jose(
-            &self.keypair,
-            Some(payload),
-            Some(&self.url),
-            Some(&nonce),
-            &directory.key_change,
-        );
-        let response = client
-            .post_jose(&directory.key_change, &body)
+        let payload = jose(&keypair, Some(payload), None, None, &directory.key_change)
             .await
             .map_err(|err| ErrorKind::ChangeAccountKey.wrap(err))?;
-        if response.is_success() {
-            let account = response
-                .body_as_json::<Account>()
-                .await
-                .map_err(|err| ErrorKind::ChangeAccountKey.wrap(err))?;
-            match account.status {
-                AccountStatus::Valid => Ok(AccountMaterial {
-                    keypair,
-                    pkcs8,
-                    url: self.url.clone(),
-                }),
-                _ => {
-                    Err(ErrorKind::ChangeAccountKey
-                        .with_msg(format!("account is {}", account.status)))
-                }
+        let response = directory
+            .signed_request(
+                &directory.key_change,
+                async |nonce| {
+                    jose(
+                        &self.keypair,
+                        Some(payload.clone()),
+                        Some(&self.url),
+                        Some(nonce),
+                        &directory.key_change,
+                    )
+                    .await
+                },
+                client,
+            )
+            .await
+            .map_err(|err| ErrorKind::ChangeAccountKey.wrap(err))?;
+        let account = response
+            .body_as_json::<Account>()
+            .await
+            .map_err(|err| ErrorKind::ChangeAccountKey.wrap(err))?;
+        match account.status {
+            AccountStatus::Valid => Ok(AccountMaterial {
+                keypair,
+                pkcs8,
+                url: self.url.clone(),
+            }),
+            _ => {
+                Err(ErrorKind::ChangeAccountKey.with_msg(format!("account is {}", account.status)))
             }
-        } else {
-            #[cfg(feature = "tracing")]
-            if let Ok(text) = response.body_as_text().await {
-                debug!(body = ?text);
+        }
+    }
+    /// [RFC8555 Account Deactivation](https://datatracker.ietf.org/doc/html/rfc8555#section-7.3.6)
+    /// Deactivate the account and return its final status.
+    /// A deactivated account can't be used anymore, and can't be reactivated.
+    #[cfg_attr(feature = "tracing", tracing::instrument(
+        name = "deactivate_account",
+        skip_all,
+        level = tracing::Level::DEBUG,
+        err(level = tracing::Level::WARN)
+    ))]
+    pub async fn deactivate<C: HttpClient<R>, R: Response>(
+        self,
+        directory: &Directory,
+        client: &C,
+    ) -> Result<AccountStatus> {
+        let payload = json!({
+            "status": "deactivated"
+        });
+        let account = directory
+            .signed_request(
+                &self.url,
+                async |nonce| {
+                    jose(
+                        &self.keypair,
+                        Some(payload.clone()),
+                        Some(&self.url),
+                        Some(nonce),
+                        &self.url,
+                    )
+                    .await
+                },
+                client,
+            )
+            .await
+            .map_err(|err| ErrorKind::DeactivateAccount.wrap(err))?
+            .body_as_json::<Account>()
+            .await
+            .map_err(|err| ErrorKind::DeactivateAccount.wrap(err))?;
+        match account.status {
+            AccountStatus::Deactivated => Ok(account.status),
+            _ => {
+                Err(ErrorKind::DeactivateAccount.with_msg(format!("account is {}", account.status)))
             }
-            #[cfg(not(feature = "tracing"))]
-            let _ = response.body_as_text();
-            Err(ErrorKind::ChangeAccountKey.into())
         }
     }
+    /// Get the account object with a POST-as-GET request to the account url.
+    #[cfg_attr(feature = "tracing", tracing::instrument(
+        name = "get_account",
+        skip_all,
+        level = tracing::Level::DEBUG,
+        err(level = tracing::Level::WARN)
+    ))]
+    pub async fn account<C: HttpClient<R>, R: Response>(
+        &self,
+        directory: &Directory,
+        client: &C,
+    ) -> Result<Account> {
+        directory
+            .signed_request(
+                &self.url,
+                async |nonce| {
+                    jose(&self.keypair, None, Some(&self.url), Some(nonce), &self.url).await
+                },
+                client,
+            )
+            .await
+            .map_err(|err| ErrorKind::GetAccount.wrap(err))?
+            .body_as_json::<Account>()
+            .await
+            .map_err(|err| ErrorKind::GetAccount.wrap(err))
+    }
+    /// [RFC 8555 Orders List](https://datatracker.ietf.org/doc/html/rfc8555#section-7.1.2.1)
+    /// Get all the orders of the account, with their status and identifiers.
+    #[cfg_attr(feature = "tracing", tracing::instrument(
+        name = "get_account_orders",
+        skip_all,
+        level = tracing::Level::DEBUG,
+        err(level = tracing::Level::WARN)
+    ))]
+    pub async fn orders<C: HttpClient<R>, R: Response>(
+        &self,
+        directory: &Directory,
+        client: &C,
+    ) -> Result<Vec<AccountOrder>> {
+        let orders_url = self
+            .account(directory, client)
+            .await
+            .map_err(|err| ErrorKind::ListOrders.wrap(err))?
+            .orders
+            .ok_or(ErrorKind::ListOrders.with_msg("the acme server doesn't list the orders"))?;
+        list_orders(&orders_url, self, directory, client).await
+    }
     /// [RFC 8555 Nonce](https://datatracker.ietf.org/doc/html/rfc8555#section-7.2)
     #[cfg_attr(feature = "tracing", tracing::instrument(
         name = "new_account",
@@ -315,56 +910,78 @@ impl AccountMaterial {
     ))]
     async fn new_account<C: HttpClient<R>, R: Response>(
         pkcs8: Vec<u8>,
-        keypair: EcdsaKeyPair,
-        contact_email: impl AsRef<str>,
+        keypair: Box<dyn Signer>,
+        contacts: Contacts,
+        eab: Option<&ExternalAccountBinding>,
         directory: &Directory,
         client: &C,
     ) -> Result<Self> {
-        let nonce = directory.new_nonce(client).await?;
-        let payload = json!({
-            "termsOfServiceAgreed": true,
-            "contact": vec![format!("mailto:{}", contact_email.as_ref())]
+        contacts.validate()?;
+        let mut payload = json!({
+            "termsOfServiceAgreed": true
         });
-        let body = jose(
-            &keypair,
-            Some(payload),
-            None,
-            Some(&nonce),
-            &directory.new_account,
-        );
-        let response = client
-            .post_jose(&directory.new_account, &body)
-            .await
-            .map_err(|err| ErrorKind::NewAccount.wrap(err))?;
-        if response.is_success() {
-            let kid = response
-                .header_value("location")
-                .ok_or(ErrorKind::NewAccount.with_msg("could not get account kid"))?;
-            let account = response
-                .body_as_json::<Account>()
-                .await
-                .map_err(|err| ErrorKind::NewAccount.wrap(err))?;
-            match account.status {
-                AccountStatus::Valid => Ok(AccountMaterial {
-                    keypair,
-                    pkcs8,
-                    url: kid,
-                }),
-                _ => Err(ErrorKind::NewAccount.with_msg(format!("account is {}", account.status))),
+        if !contacts.uris().is_empty() {
+            payload["contact"] = json!(contacts.uris());
+        }
+        match eab {
+            Some(eab) => {
+                payload["externalAccountBinding"] =
+                    eab_jose(&eab.key_id, &eab.hmac_key, &keypair, &directory.new_account);
             }
-        } else {
-            #[cfg(feature = "tracing")]
-            if let Ok(text) = response.body_as_text().await {
-                debug!(body = ?text);
+            None if directory.meta().external_account_required => {
+                return Err(ErrorKind::ExternalAccountRequired.into());
             }
-            #[cfg(not(feature = "tracing"))]
-            let _ = response.body_as_text();
-            Err(ErrorKind::NewAccount.into())
+            None => {}
+        }
+        let response = directory
+            .signed_request(
+                &directory.new_account,
+                async |nonce| {
+                    jose(
+                        &keypair,
+                        Some(payload.clone()),
+                        None,
+                        Some(nonce),
+                        &directory.new_account,
+                    )
+                    .await
+                },
+                client,
+            )
+            .await
+            .map_err(|err| ErrorKind::NewAccount.wrap(err))?;
+        let kid = response
+            .header_value("location")
+            .ok_or(ErrorKind::NewAccount.with_msg("could not get account kid"))?;
+        let account = response
+            .body_as_json::<Account>()
+            .await
+            .map_err(|err| ErrorKind::NewAccount.wrap(err))?;
+        match account.status {
+            AccountStatus::Valid => Ok(AccountMaterial {
+                keypair,
+                pkcs8,
+                url: kid,
+            }),
+            _ => Err(ErrorKind::NewAccount.with_msg(format!("account is {}", account.status))),
         }
     }
 }
 
-mod base64 {
+#[cfg(test)]
+impl AccountMaterial {
+    /// An account with a new P-256 key, for requests to the mock client.
+    pub(crate) fn mock(url: impl Into<String>) -> Self {
+        let pkcs8 = generate_pkcs8_ecdsa_keypair();
+        Self {
+            keypair: Box::new(AccountKey::from_pkcs8(&pkcs8).expect("failed to extract keypair")),
+            pkcs8,
+            url: url.into(),
+        }
+    }
+}
+
+pub(crate) mod base64 {
     use base64::Engine;
     use serde::{Deserialize, Deserializer, Serialize, Serializer};
 
@@ -384,18 +1001,24 @@ mod base64 {
 #[cfg(test)]
 mod test {
     use super::*;
-    use crate::ecdsa::{generate_pkcs8_ecdsa_keypair, keypair_from_pkcs8};
+    use crate::ecdsa::generate_pkcs8_ecdsa_keypair;
+    use crate::key::test::{EC_PRIVATE_KEY_PEM, RSA_PRIVATE_KEY_JWK};
+    use crate::key::{MockSigner, PublicKey};
+    use crate::mock::{MockClient, MockResponse, decode_payload, directory};
+    use ring::signature::{ECDSA_P256_SHA256_FIXED, UnparsedPublicKey};
     use rustls::crypto;
     use test_tracing::test;
 
+    const ACCOUNT_URL: &str = "https://example.com/acme/acct/evOfKhNU60wg";
+
     #[test]
     fn test_account_material_serialization() {
         let pkcs8 = generate_pkcs8_ecdsa_keypair();
-        let keypair = keypair_from_pkcs8(&pkcs8).unwrap();
+        let keypair = AccountKey::from_pkcs8(&pkcs8).unwrap();
         let kid = "kid";
         let original = AccountMaterial {
             pkcs8,
-            keypair,
+            keypair: Box::new(keypair),
             url: kid.into(),
         };
         let json = original.to_json();
@@ -406,7 +1029,79 @@ mod test {
                 .unwrap();
         assert_eq!(deserialized.url, kid);
         assert_eq!(&original.pkcs8, &deserialized.pkcs8);
-        let _ = keypair_from_pkcs8(&deserialized.pkcs8).unwrap();
+        let _ = AccountKey::from_pkcs8(&deserialized.pkcs8).unwrap();
+    }
+
+    #[test]
+    fn test_external_account_binding() {
+        let pkcs8 = generate_pkcs8_ecdsa_keypair();
+        let keypair = AccountKey::from_pkcs8(&pkcs8).unwrap();
+        let eab = ExternalAccountBinding::from_base64(
+            "kid-1",
+            "zWNDZM6eQGHWpSRTPal5eIUYFTu7EajVIoguysqZ9wG44nMEtx3MUAsUDkMTQ12W",
+        )
+        .unwrap();
+        let url = "https://example.com/acme/new-account";
+        let jws = eab_jose(&eab.key_id, &eab.hmac_key, &keypair, url);
+        let protected = jws["protected"].as_str().unwrap();
+        let payload = jws["payload"].as_str().unwrap();
+        let header: serde_json::Value =
+            serde_json::from_slice(&BASE64_URL_SAFE_NO_PAD.decode(protected).unwrap()).unwrap();
+        assert_eq!(
+            header,
+            json!({ "alg": "HS256", "kid": "kid-1", "url": url })
+        );
+        assert_eq!(
+            BASE64_URL_SAFE_NO_PAD.decode(payload).unwrap(),
+            serde_json::to_vec(&keypair.jwk()).unwrap()
+        );
+        let key = ring::hmac::Key::new(ring::hmac::HMAC_SHA256, &eab.hmac_key);
+        ring::hmac::verify(
+            &key,
+            format!("{protected}.{payload}").as_bytes(),
+            &BASE64_URL_SAFE_NO_PAD
+                .decode(jws["signature"].as_str().unwrap())
+                .unwrap(),
+        )
+        .unwrap();
+        assert!(ExternalAccountBinding::from_base64("kid-1", "not base64!").is_err());
+    }
+
+    #[test]
+    fn test_contacts() {
+        assert_eq!(
+            Contacts::from("admin@example.org").uris(),
+            ["mailto:admin@example.org"]
+        );
+        let contacts = Contacts::from(vec![
+            "mailto:cert-admin@example.org",
+            " admin@example.org ",
+            "tel:+12025550101",
+        ]);
+        assert_eq!(
+            contacts.uris(),
+            [
+                "mailto:cert-admin@example.org",
+                "mailto:admin@example.org",
+                "tel:+12025550101"
+            ]
+        );
+        assert!(contacts.validate().is_ok());
+        assert!(Contacts::none().uris().is_empty());
+        assert!(Contacts::none().validate().is_ok());
+        for invalid in [
+            "",
+            "admin",
+            "mailto:admin@",
+            "mailto:@example.org",
+            "mailto:admin@example.org?subject=acme",
+            "mailto:admin@example.org,cert-admin@example.org",
+            "admin @example.org",
+            "tel:",
+        ] {
+            let err = Contacts::from(invalid).validate().unwrap_err();
+            assert!(matches!(err.kind, ErrorKind::InvalidContact { .. }));
+        }
     }
 
     #[test]
@@ -423,6 +1118,258 @@ mod test {
         .unwrap();
         let deserialized = serde_json::from_str::<Account>(json.as_str()).unwrap();
         assert_eq!(deserialized.status, AccountStatus::Valid);
+        assert_eq!(
+            deserialized.contact,
+            vec!["mailto:cert-admin@example.org", "mailto:admin@example.org"]
+        );
+        assert_eq!(deserialized.terms_of_service_agreed, Some(true));
+        assert_eq!(
+            deserialized.orders.as_deref(),
+            Some("https://example.com/acme/orders/rzGoeA")
+        );
+        assert!(deserialized.external_account_key_id().is_none());
+    }
+
+    #[test]
+    fn test_account_external_account_binding() {
+        let keypair = AccountKey::from_pkcs8(&generate_pkcs8_ecdsa_keypair()).unwrap();
+        let eab = ExternalAccountBinding::new("kid-1", b"hmac".to_vec());
+        let json = json!({
+            "status": "valid",
+            "externalAccountBinding": eab_jose(
+                &eab.key_id,
+                &eab.hmac_key,
+                &keypair,
+                "https://example.com/acme/new-account"
+            )
+        });
+        let deserialized = serde_json::from_value::<Account>(json).unwrap();
+        assert!(deserialized.contact.is_empty());
+        assert_eq!(
+            deserialized.external_account_key_id().as_deref(),
+            Some("kid-1")
+        );
+    }
+
+    #[test(tokio::test)]
+    async fn test_restore_rejected() {
+        let client =
+            MockClient::new().on(ACCOUNT_URL, MockResponse::new(403).with_body("forbidden"));
+        let err = AccountMaterial::from_json(
+            AccountMaterial::mock(ACCOUNT_URL).to_json(),
+            Contacts::none(),
+            &directory(),
+            &client,
+        )
+        .await
+        .err()
+        .unwrap();
+        assert!(matches!(err.kind, ErrorKind::GetAccount));
+        assert!(err.problem().is_none());
+        let client = MockClient::new().on(
+            ACCOUNT_URL,
+            MockResponse::problem(403, "userActionRequired", "Terms of service have changed")
+                .with_header(
+                    "link",
+                    r#"<https://example.com/acme/terms/2017-6-02>;rel="terms-of-service""#,
+                ),
+        );
+        let err = AccountMaterial::from_json(
+            AccountMaterial::mock(ACCOUNT_URL).to_json(),
+            Contacts::none(),
+            &directory(),
+            &client,
+        )
+        .await
+        .err()
+        .unwrap();
+        assert!(matches!(
+            err.kind,
+            ErrorKind::UserActionRequired { terms_of_service: Some(ref it) }
+                if it == "https://example.com/acme/terms/2017-6-02"
+        ));
+    }
+
+    #[test(tokio::test)]
+    async fn test_restore_not_found() {
+        let new_account_url = "https://example.com/acme/acct/ThV9bpGxLa4";
+        let terms_of_service = "https://example.com/acme/terms/2017-5-30";
+        let mock_client = || {
+            MockClient::new()
+                .on(
+                    ACCOUNT_URL,
+                    MockResponse::problem(400, "accountDoesNotExist", "no such account"),
+                )
+                .on(
+                    "https://example.com/acme/new-account",
+                    MockResponse::json(json!({ "status": "valid" }))
+                        .with_header("location", new_account_url),
+                )
+        };
+        let terms_directory = serde_json::from_value::<Directory>(json!({
+            "newNonce": crate::mock::NEW_NONCE_URL,
+            "newAccount": "https://example.com/acme/new-account",
+            "newOrder": "https://example.com/acme/new-order",
+            "revokeCert": "https://example.com/acme/revoke-cert",
+            "keyChange": "https://example.com/acme/key-change",
+            "meta": { "termsOfService": terms_of_service }
+        }))
+        .unwrap();
+        let json = AccountMaterial::mock(ACCOUNT_URL).to_json();
+        // Creating the account again requires agreeing to the terms of service.
+        for agreement in [None, Some(false)] {
+            let client = mock_client();
+            let result = match agreement {
+                None => {
+                    AccountMaterial::from_json(&json, Contacts::none(), &terms_directory, &client)
+                        .await
+                        .err()
+                }
+                Some(agreed) => AccountMaterial::from_json_with_agreement(
+                    &json,
+                    Contacts::none(),
+                    None,
+                    |_| agreed,
+                    &terms_directory,
+                    &client,
+                )
+                .await
+                .err(),
+            };
+            assert!(matches!(
+                result.unwrap().kind,
+                ErrorKind::UserActionRequired { terms_of_service: Some(ref it) }
+                    if it == terms_of_service
+            ));
+            assert!(
+                client
+                    .payloads("https://example.com/acme/new-account")
+                    .is_empty()
+            );
+        }
+        let client = mock_client();
+        let agreed = std::sync::Mutex::new(None);
+        let account = AccountMaterial::from_json_with_agreement(
+            &json,
+            Contacts::none(),
+            None,
+            |it| {
+                *agreed.lock().unwrap() = Some(it.to_string());
+                true
+            },
+            &terms_directory,
+            &client,
+        )
+        .await
+        .unwrap();
+        assert_eq!(account.url, new_account_url);
+        assert_eq!(
+            agreed.into_inner().unwrap().as_deref(),
+            Some(terms_of_service)
+        );
+        assert_eq!(
+            client.payloads("https://example.com/acme/new-account"),
+            vec![json!({ "termsOfServiceAgreed": true })]
+        );
+        // Without terms of service, there is nothing to agree to.
+        let client = mock_client();
+        let account = AccountMaterial::from_json(&json, Contacts::none(), &directory(), &client)
+            .await
+            .unwrap();
+        assert_eq!(account.url, new_account_url);
+    }
+
+    #[test(tokio::test)]
+    async fn test_restore_contacts() {
+        let client = MockClient::new().on(
+            ACCOUNT_URL,
+            MockResponse::json(json!({
+                "status": "valid",
+                "contact": ["mailto:admin@example.org"]
+            })),
+        );
+        let json = AccountMaterial::mock(ACCOUNT_URL).to_json();
+        // The registered contacts are kept when none or the same ones are specified.
+        for contacts in [Contacts::none(), Contacts::from("admin@example.org")] {
+            AccountMaterial::from_json(&json, contacts, &directory(), &client)
+                .await
+                .unwrap();
+        }
+        assert_eq!(
+            client.payloads(ACCOUNT_URL),
+            vec![json!({ "onlyReturnExisting": true }); 2]
+        );
+        AccountMaterial::from_json(&json, "ops@example.org", &directory(), &client)
+            .await
+            .unwrap();
+        assert_eq!(
+            client.payloads(ACCOUNT_URL).last(),
+            Some(&json!({ "contact": ["mailto:ops@example.org"] }))
+        );
+    }
+
+    #[test(tokio::test)]
+    async fn test_deactivate() {
+        let client = MockClient::new().on(
+            ACCOUNT_URL,
+            MockResponse::json(json!({ "status": "deactivated" })),
+        );
+        assert_eq!(
+            AccountMaterial::mock(ACCOUNT_URL)
+                .deactivate(&directory(), &client)
+                .await
+                .unwrap(),
+            AccountStatus::Deactivated
+        );
+        assert_eq!(
+            client.payloads(ACCOUNT_URL),
+            vec![json!({ "status": "deactivated" })]
+        );
+        let client = MockClient::new().on(
+            ACCOUNT_URL,
+            MockResponse::json(json!({ "status": "valid" })),
+        );
+        let err = AccountMaterial::mock(ACCOUNT_URL)
+            .deactivate(&directory(), &client)
+            .await
+            .err()
+            .unwrap();
+        assert!(matches!(err.kind, ErrorKind::DeactivateAccount));
+    }
+
+    #[test(tokio::test)]
+    async fn test_signer_account_json() {
+        let client = MockClient::new().on(
+            ACCOUNT_URL,
+            MockResponse::json(json!({ "status": "valid", "contact": [] })),
+        );
+        let account = AccountMaterial {
+            keypair: Box::new(MockSigner::new(KeyAlgorithm::Es256).unwrap()),
+            pkcs8: Vec::new(),
+            url: ACCOUNT_URL.to_string(),
+        };
+        let json = account.to_json();
+        assert_eq!(json, json!({ "url": ACCOUNT_URL }).to_string());
+        let err = AccountMaterial::from_json(&json, Contacts::none(), &directory(), &client)
+            .await
+            .err()
+            .unwrap();
+        assert!(matches!(err.kind, ErrorKind::DeserializeAccount));
+        let signer = MockSigner::new(KeyAlgorithm::Es256).unwrap();
+        let restored = AccountMaterial::from_json_with_signer(
+            &json,
+            signer,
+            Contacts::none(),
+            None,
+            &directory(),
+            &client,
+        )
+        .await
+        .unwrap();
+        assert_eq!(restored.url, ACCOUNT_URL);
+        assert_eq!(restored.to_json(), json);
+        // The restored account is checked with the ACME server.
+        assert!(!client.payloads(ACCOUNT_URL).is_empty());
     }
 
     #[cfg(feature = "reqwest")]
@@ -436,7 +1383,7 @@ mod test {
         )
         .await
         .unwrap();
-        let created = AccountMaterial::from("void@programingjd.me", &directory, &acme.client)
+        let created = AccountMaterial::from("void@programingjd.me", None, &directory, &acme.client)
             .await
             .unwrap();
         tracing::trace!(account_url = &created.url);
@@ -450,7 +1397,187 @@ mod test {
         .unwrap();
         assert_eq!(account.url, created.url);
         assert_eq!(account.pkcs8, created.pkcs8);
+        let details = account.account(&directory, &acme.client).await.unwrap();
+        assert_eq!(details.status, AccountStatus::Valid);
         let updated = account.update_key(&directory, &acme.client).await.unwrap();
         assert_eq!(updated.url, created.url);
+        let pkcs8 = crate::key::generate_pkcs8_keypair(KeyAlgorithm::Es384).unwrap();
+        let updated = updated
+            .update_key_with_pkcs8(pkcs8.clone(), &directory, &acme.client)
+            .await
+            .unwrap();
+        assert_eq!(updated.url, created.url);
+        assert_eq!(updated.pkcs8, pkcs8);
+        assert_eq!(updated.algorithm(), KeyAlgorithm::Es384);
+        assert_eq!(
+            updated.deactivate(&directory, &acme.client).await.unwrap(),
+            AccountStatus::Deactivated
+        );
+    }
+
+    /// Verify the signature of a JWS with the P-256 key and return its protected header.
+    fn verify_es256(jws: &Value, key: &dyn Signer) -> Value {
+        let PublicKey::Ec { x, y } = key.public_key() else {
+            panic!("expected an EC public key");
+        };
+        let protected = jws["protected"].as_str().unwrap();
+        let payload = jws["payload"].as_str().unwrap();
+        let signature = BASE64_URL_SAFE_NO_PAD
+            .decode(jws["signature"].as_str().unwrap())
+            .unwrap();
+        UnparsedPublicKey::new(&ECDSA_P256_SHA256_FIXED, [&[4], &x[..], &y[..]].concat())
+            .verify(format!("{protected}.{payload}").as_bytes(), &signature)
+            .expect("invalid signature");
+        serde_json::from_slice(&BASE64_URL_SAFE_NO_PAD.decode(protected).unwrap()).unwrap()
+    }
+
+    #[test(tokio::test)]
+    async fn test_update_key() {
+        let key_change_url = "https://example.com/acme/key-change";
+        let client = MockClient::new().on(
+            key_change_url,
+            MockResponse::json(json!({ "status": "valid" })),
+        );
+        let account = AccountMaterial::mock(ACCOUNT_URL);
+        let pkcs8 = generate_pkcs8_ecdsa_keypair();
+        let updated = account
+            .update_key_with_pkcs8(pkcs8.clone(), &directory(), &client)
+            .await
+            .unwrap();
+        let new_key = AccountKey::from_pkcs8(&pkcs8).unwrap();
+        assert_eq!(updated.pkcs8, pkcs8);
+        assert_eq!(updated.keypair.jwk(), new_key.jwk());
+        assert_eq!(updated.url, ACCOUNT_URL);
+
+        let bodies = client.bodies(key_change_url);
+        assert_eq!(bodies.len(), 1);
+        // The outer JWS is signed by the old key, and identifies the account.
+        let outer = verify_es256(&bodies[0], &account.keypair);
+        assert_eq!(outer["kid"], ACCOUNT_URL);
+        assert_eq!(outer["url"], key_change_url);
+        assert!(outer.get("jwk").is_none());
+        // The inner JWS is signed by the new key, and carries its jwk.
+        let inner_jws = decode_payload(&bodies[0]);
+        let inner = verify_es256(&inner_jws, &new_key);
+        assert_eq!(inner["jwk"], json!(new_key.jwk()));
+        assert_eq!(inner["url"], key_change_url);
+        assert!(inner.get("kid").is_none());
+        assert!(inner.get("nonce").is_none());
+        assert_eq!(
+            decode_payload(&inner_jws),
+            json!({
+                "account": ACCOUNT_URL,
+                "oldKey": account.keypair.jwk()
+            })
+        );
+    }
+
+    #[test(tokio::test)]
+    async fn test_update_key_with_current_key() {
+        let client = MockClient::new();
+        let account = AccountMaterial::mock(ACCOUNT_URL);
+        let err = account
+            .update_key_with_pkcs8(account.pkcs8.clone(), &directory(), &client)
+            .await
+            .err()
+            .unwrap();
+        assert!(matches!(err.kind, ErrorKind::ChangeAccountKey));
+        assert!(client.requests().is_empty());
+    }
+
+    #[test(tokio::test)]
+    async fn test_import_certbot_account() {
+        // The registration url saved by certbot can be outdated (ACME v1).
+        let regr_json = json!({
+            "body": { "contact": ["mailto:admin@example.org"] },
+            "uri": "https://example.com/acme/reg/evOfKhNU60wg"
+        })
+        .to_string();
+        let client = MockClient::new().on(
+            "https://example.com/acme/new-account",
+            MockResponse::json(json!({ "status": "valid" })).with_header("location", ACCOUNT_URL),
+        );
+        let account =
+            AccountMaterial::from_certbot(RSA_PRIVATE_KEY_JWK, regr_json, &directory(), &client)
+                .await
+                .unwrap();
+        assert_eq!(account.url, ACCOUNT_URL);
+        assert_eq!(account.algorithm(), KeyAlgorithm::Rs256);
+        // The account is looked up by its key, and never created.
+        assert_eq!(
+            client.requests(),
+            vec![(
+                "https://example.com/acme/new-account".to_string(),
+                json!({ "onlyReturnExisting": true })
+            )]
+        );
+    }
+
+    #[test(tokio::test)]
+    async fn test_import_lego_account() {
+        let account_json = json!({
+            "email": "admin@example.org",
+            "registration": {
+                "body": { "status": "valid" },
+                "uri": ACCOUNT_URL
+            }
+        })
+        .to_string();
+        let client = MockClient::new().on(
+            "https://example.com/acme/new-account",
+            MockResponse::json(json!({ "status": "valid" })),
+        );
+        let account =
+            AccountMaterial::from_lego(EC_PRIVATE_KEY_PEM, &account_json, &directory(), &client)
+                .await
+                .unwrap();
+        assert_eq!(account.url, ACCOUNT_URL);
+        assert_eq!(account.algorithm(), KeyAlgorithm::Es256);
+        let client = MockClient::new().on(
+            "https://example.com/acme/new-account",
+            MockResponse::problem(400, "accountDoesNotExist", "no account for this key"),
+        );
+        let err =
+            AccountMaterial::from_lego(EC_PRIVATE_KEY_PEM, &account_json, &directory(), &client)
+                .await
+                .err()
+                .unwrap();
+        assert!(matches!(err.kind, ErrorKind::ImportAccount));
+        assert_eq!(
+            err.problem().unwrap().kind,
+            ProblemType::AccountDoesNotExist
+        );
+        assert_eq!(client.requests().len(), 1);
+    }
+
+    #[test(tokio::test)]
+    async fn test_import_invalid_account() {
+        let client = MockClient::new();
+        let regr_json = r#"{"body":{},"uri":"https://example.com/acme/acct/evOfKhNU60wg"}"#;
+        let err = AccountMaterial::from_certbot(
+            r#"{"kty":"EC","crv":"P-521","x":"AA","y":"AA","d":"AA"}"#,
+            regr_json,
+            &directory(),
+            &client,
+        )
+        .await
+        .err()
+        .unwrap();
+        assert!(matches!(err.kind, ErrorKind::ImportAccount));
+        assert!(
+            err.to_string()
+                .contains("unsupported key type \"EC P-521\"")
+        );
+        let err = AccountMaterial::from_lego(
+            "",
+            r#"{"email":"void@programingjd.me","registration":null}"#,
+            &directory(),
+            &client,
+        )
+        .await
+        .err()
+        .unwrap();
+        assert!(err.to_string().contains("not registered"));
+        assert!(client.requests().is_empty());
     }
 }
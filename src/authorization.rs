@@ -1,26 +1,50 @@
 use crate::account::AccountMaterial;
-use crate::challenge::Challenge;
+use crate::challenge::{Challenge, ChallengeStatus, ChallengeType};
 use crate::client::{HttpClient, Response};
 use crate::directory::Directory;
 use crate::errors::{ErrorKind, Result};
 use crate::jose::jose;
+use crate::order::{Identifier, poll_delay};
+use crate::resolver::{CertResolver, DomainResolver};
+use futures::StreamExt;
+use futures::future::{Either, select};
+use futures::stream::FuturesUnordered;
+use futures_timer::Delay;
 use serde::Deserialize;
+use serde_json::json;
 use std::fmt::Debug;
-#[cfg(feature = "tracing")]
-use tracing::debug;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use time::OffsetDateTime;
+
+/// Maximum time spent polling pre-authorizations once the challenges have been accepted.
+const VALIDATION_DEADLINE: Duration = Duration::from_secs(60);
 
 /// [RFC 8555 Authorization](https://datatracker.ietf.org/doc/html/rfc8555#section-7.1.4)
 #[derive(Deserialize, Debug)]
 pub(crate) struct Authorization {
-    pub(crate) identifier: crate::order::Identifier,
+    pub(crate) identifier: Identifier,
     pub(crate) challenges: Vec<Challenge>,
     #[serde(flatten)]
     pub(crate) status: AuthorizationStatus,
+    #[serde(default, with = "time::serde::rfc3339::option")]
+    pub(crate) expires: Option<OffsetDateTime>,
+}
+
+/// The result of a [pre-authorization](https://datatracker.ietf.org/doc/html/rfc8555#section-7.4.1).
+/// Orders for the identifier don't need a new validation until the authorization expires.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PreAuthorization {
+    pub url: String,
+    pub identifier: Identifier,
+    pub status: AuthorizationStatus,
+    pub expires: Option<OffsetDateTime>,
 }
 
-#[derive(Deserialize, Debug, PartialEq, Eq)]
+/// [RFC 8555 Authorization States](https://datatracker.ietf.org/doc/html/rfc8555#page-32)
+#[derive(Deserialize, Debug, Clone, PartialEq, Eq)]
 #[serde(tag = "status")]
-pub(crate) enum AuthorizationStatus {
+pub enum AuthorizationStatus {
     #[serde(rename = "pending")]
     Pending,
     #[serde(rename = "valid")]
@@ -50,44 +74,404 @@ impl Authorization {
         client: &C,
     ) -> Result<Authorization> {
         let url = url.as_ref();
-        let nonce = directory.new_nonce(client).await?;
-        let body = jose(
-            &account.keypair,
-            None,
-            Some(&account.url),
-            Some(&nonce),
-            url,
-        );
-        let response = client
-            .post_jose(url, &body)
+        let response = directory
+            .signed_request(
+                url,
+                async |nonce| {
+                    jose(&account.keypair, None, Some(&account.url), Some(nonce), url).await
+                },
+                client,
+            )
             .await
             .map_err(|err| ErrorKind::GetAuthorization.wrap(err))?;
-        if response.is_success() {
-            response
-                .body_as_json::<Authorization>()
-                .await
-                .map_err(|err| ErrorKind::GetAuthorization.wrap(err))
-        } else {
-            #[cfg(feature = "tracing")]
-            if let Ok(text) = response.body_as_text().await {
-                debug!(body = ?text);
+        response
+            .body_as_json::<Authorization>()
+            .await
+            .map_err(|err| ErrorKind::GetAuthorization.wrap(err))
+    }
+    /// [RFC 8555 Pre-Authorization](https://datatracker.ietf.org/doc/html/rfc8555#section-7.4.1)
+    /// Create a new authorization and return its url.
+    #[cfg_attr(feature = "tracing", tracing::instrument(
+        name = "new_authorization",
+        skip(account,directory,client),
+        level = tracing::Level::DEBUG,
+        ret(level = tracing::Level::DEBUG),
+        err(level = tracing::Level::WARN)
+    ))]
+    async fn new_authorization<C: HttpClient<R>, R: Response>(
+        identifier: &Identifier,
+        account: &AccountMaterial,
+        directory: &Directory,
+        client: &C,
+    ) -> Result<String> {
+        let url = directory.new_authz.as_ref().ok_or(
+            ErrorKind::NewAuthorization
+                .with_msg("the acme server doesn't support pre-authorization"),
+        )?;
+        let payload = json!({
+            "identifier": identifier
+        });
+        let response = directory
+            .signed_request(
+                url,
+                async |nonce| {
+                    jose(
+                        &account.keypair,
+                        Some(payload.clone()),
+                        Some(&account.url),
+                        Some(nonce),
+                        url,
+                    )
+                    .await
+                },
+                client,
+            )
+            .await
+            .map_err(|err| ErrorKind::NewAuthorization.wrap(err))?;
+        response
+            .header_value("location")
+            .ok_or(ErrorKind::NewAuthorization.with_msg("could not get authorization url"))
+    }
+    /// [RFC 8555 Deactivating an Authorization](https://datatracker.ietf.org/doc/html/rfc8555#section-7.5.2)
+    #[cfg_attr(feature = "tracing", tracing::instrument(
+        name = "deactivate_authorization",
+        skip(account,directory,client),
+        level = tracing::Level::DEBUG,
+        ret(level = tracing::Level::DEBUG),
+        err(level = tracing::Level::WARN)
+    ))]
+    async fn deactivate<C: HttpClient<R>, R: Response>(
+        url: &str,
+        account: &AccountMaterial,
+        directory: &Directory,
+        client: &C,
+    ) -> Result<AuthorizationStatus> {
+        let payload = json!({
+            "status": "deactivated"
+        });
+        let response = directory
+            .signed_request(
+                url,
+                async |nonce| {
+                    jose(
+                        &account.keypair,
+                        Some(payload.clone()),
+                        Some(&account.url),
+                        Some(nonce),
+                        url,
+                    )
+                    .await
+                },
+                client,
+            )
+            .await
+            .map_err(|err| ErrorKind::DeactivateAuthorization.wrap(err))?;
+        response
+            .body_as_json::<Authorization>()
+            .await
+            .map(|it| it.status)
+            .map_err(|err| ErrorKind::DeactivateAuthorization.wrap(err))
+    }
+}
+
+/// Deactivate the authorizations that match the filter.
+/// Only pending and valid authorizations can be deactivated, the others are left as is.
+/// Returns the (final) status of each authorization.
+pub(crate) async fn deactivate_authorizations<C: HttpClient<R>, R: Response>(
+    urls: &[String],
+    filter: impl Fn(&Authorization) -> bool,
+    account: &AccountMaterial,
+    directory: &Directory,
+    client: &C,
+) -> Result<Vec<AuthorizationStatus>> {
+    let filter = &filter;
+    let futures: Vec<_> = urls
+        .iter()
+        .map(|url| async move {
+            let authorization = Authorization::authorize(url, account, directory, client).await?;
+            match authorization.status {
+                AuthorizationStatus::Pending | AuthorizationStatus::Valid
+                    if filter(&authorization) =>
+                {
+                    Authorization::deactivate(url, account, directory, client).await
+                }
+                status => Ok(status),
+            }
+        })
+        .collect();
+    futures::future::try_join_all(futures).await
+}
+
+/// Pre-authorize the identifiers with the tls-alpn-01 challenge, and wait for the validations.
+#[cfg_attr(feature = "tracing", tracing::instrument(
+    name = "pre_authorize",
+    skip(account,directory,resolver,client),
+    level = tracing::Level::DEBUG,
+    err(level = tracing::Level::WARN)
+))]
+pub(crate) async fn pre_authorize<C: HttpClient<R>, R: Response>(
+    identifiers: Vec<Identifier>,
+    account: &AccountMaterial,
+    directory: &Directory,
+    resolver: &CertResolver,
+    client: &C,
+) -> Result<Vec<PreAuthorization>> {
+    let futures: Vec<_> = identifiers
+        .iter()
+        .map(|it| Authorization::new_authorization(it, account, directory, client))
+        .collect();
+    let urls = futures::future::try_join_all(futures).await?;
+    let result = accept_challenges(&urls, account, directory, resolver, client).await;
+    resolver.clear_challenges(identifiers.iter());
+    result?;
+    // The validations can still be in progress.
+    wait_for_validations(
+        urls,
+        Instant::now() + VALIDATION_DEADLINE,
+        account,
+        directory,
+        client,
+    )
+    .await
+}
+
+/// Poll the authorizations until none of them is pending,
+/// and fail if some are still pending at the deadline.
+async fn wait_for_validations<C: HttpClient<R>, R: Response>(
+    urls: Vec<String>,
+    deadline: Instant,
+    account: &AccountMaterial,
+    directory: &Directory,
+    client: &C,
+) -> Result<Vec<PreAuthorization>> {
+    loop {
+        let futures: Vec<_> = urls
+            .iter()
+            .map(|url| Authorization::authorize(url, account, directory, client))
+            .collect();
+        let authorizations = futures::future::try_join_all(futures).await?;
+        if authorizations
+            .iter()
+            .all(|it| it.status != AuthorizationStatus::Pending)
+        {
+            return Ok(urls
+                .into_iter()
+                .zip(authorizations)
+                .map(|(url, it)| PreAuthorization {
+                    url,
+                    identifier: it.identifier,
+                    status: it.status,
+                    expires: it.expires,
+                })
+                .collect());
+        }
+        let Some(delay) = poll_delay(None, Instant::now(), deadline) else {
+            let pending: Vec<String> = authorizations
+                .iter()
+                .filter(|it| it.status == AuthorizationStatus::Pending)
+                .map(|it| it.identifier.to_string())
+                .collect();
+            return Err(ErrorKind::Challenge.with_msg(format!(
+                "timed out waiting for the validation of {}",
+                pending.join(", ")
+            )));
+        };
+        Delay::new(delay).await;
+    }
+}
+
+/// Get the authorizations,
+/// setup the resolver to respond to the tls-alpn-01 challenges,
+/// notify the acme server and wait for the validations.
+pub(crate) async fn accept_challenges<C: HttpClient<R>, R: Response>(
+    authorizations: &[String],
+    account: &AccountMaterial,
+    directory: &Directory,
+    resolver: &CertResolver,
+    client: &C,
+) -> Result<()> {
+    // Get the challenges for all the authorizations.
+    let futures: Vec<_> = authorizations
+        .iter()
+        .map(|url| Authorization::authorize(url, account, directory, client))
+        .collect();
+    let authorizations = futures::future::try_join_all(futures).await?;
+    // We can stop early if one of the authorizations failed.
+    if authorizations.iter().any(|it| {
+        !matches!(
+            it.status,
+            AuthorizationStatus::Valid | AuthorizationStatus::Pending
+        )
+    }) {
+        return Err(ErrorKind::InvalidAuthorization.into());
+    }
+    // Gather all the pending authorizations, and for each of them, select the tls-alpn-01 challenge
+    // and setup the resolver to respond to the validation request.
+    let mut pending_challenges = FuturesUnordered::<_>::new();
+    let guard = resolver.map.pin();
+    for authorization in authorizations {
+        let domain_name = authorization.identifier.to_string();
+        let server_name = authorization.identifier.server_name();
+        if matches!(authorization.status, AuthorizationStatus::Pending) {
+            for ref challenge in authorization.challenges {
+                if matches!(challenge.kind, ChallengeType::TlsAlpn01) {
+                    // A resumed order or a pre-authorization could be for identifiers
+                    // that are not managed.
+                    let resolver = guard.get(&server_name).ok_or_else(|| {
+                        ErrorKind::Challenge
+                            .with_msg(format!("{domain_name} is not managed by the resolver"))
+                    })?;
+                    let (sender, receiver) = flume::bounded(1);
+                    let resolver = DomainResolver {
+                        key: Arc::new(resolver.key.as_ref().clone()),
+                        challenge_key: Some(Arc::new(Challenge::certificate(
+                            &domain_name,
+                            &challenge.authorization_key(account),
+                        )?)),
+                        notifier: Some(sender),
+                    };
+                    guard.insert(server_name.clone(), resolver);
+                    match challenge.accept(account, directory, client).await?.status {
+                        ChallengeStatus::Processing | ChallengeStatus::Pending => {
+                            pending_challenges.push(receiver.into_recv_async())
+                        }
+                        ChallengeStatus::Valid => {}
+                        ChallengeStatus::Invalid => {
+                            return Err(ErrorKind::Challenge.with_msg("challenge is invalid"));
+                        }
+                    }
+                }
+            }
+        }
+    }
+    // Wait for the ACME server to call our server for all the pending challenges.
+    // Timeout after 2 mins.
+    let mut delay = Delay::new(Duration::from_secs(120));
+    loop {
+        let next = pending_challenges.next();
+        match select(delay, next).await {
+            Either::Left(_) => {
+                return Err(ErrorKind::Challenge.into());
+            }
+            Either::Right((result, unresolved_delay)) => {
+                match result {
+                    None => break,
+                    Some(Err(_)) => return Err(ErrorKind::Challenge.into()),
+                    _ => {}
+                }
+                delay = unresolved_delay;
             }
-            #[cfg(not(feature = "tracing"))]
-            let _ = response.body_as_text();
-            Err(ErrorKind::GetAuthorization.into())
         }
     }
+    Ok(())
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
-    use crate::challenge::{ChallengeStatus, ChallengeType};
-    use crate::order::Identifier;
+    use crate::mock::{MockClient, MockResponse, directory};
     use rustls::crypto;
-    use serde_json::json;
+    use serde_json::{Value, json};
     use test_tracing::test;
 
+    const ACCOUNT_URL: &str = "https://example.com/acme/acct/evOfKhNU60wg";
+
+    fn authorization_json(domain_name: &str, status: &str) -> Value {
+        json!({
+            "status": status,
+            "identifier": { "type": "dns", "value": domain_name },
+            "challenges": []
+        })
+    }
+
+    #[test(tokio::test)]
+    async fn test_deactivate() {
+        let _ = crypto::ring::default_provider().install_default();
+        let url = "https://example.com/acme/authz/PAniVnsZcis";
+        let client = MockClient::new().on(
+            url,
+            MockResponse::json(authorization_json("example.org", "deactivated")),
+        );
+        let account = AccountMaterial::mock(ACCOUNT_URL);
+        let status = Authorization::deactivate(url, &account, &directory(), &client)
+            .await
+            .unwrap();
+        assert_eq!(status, AuthorizationStatus::Deactivated);
+        assert_eq!(
+            client.payloads(url),
+            vec![json!({ "status": "deactivated" })]
+        );
+        let client = MockClient::new().on(
+            url,
+            MockResponse::problem(403, "unauthorized", "not the owner of the authorization"),
+        );
+        let err = Authorization::deactivate(url, &account, &directory(), &client)
+            .await
+            .unwrap_err();
+        assert!(matches!(err.kind(), ErrorKind::DeactivateAuthorization));
+    }
+
+    #[test(tokio::test)]
+    async fn test_deactivate_authorizations() {
+        let _ = crypto::ring::default_provider().install_default();
+        let urls: Vec<String> = (0..4)
+            .map(|i| format!("https://example.com/acme/authz/{i}"))
+            .collect();
+        let client = MockClient::new()
+            .on(
+                &urls[0],
+                MockResponse::json(authorization_json("example.org", "pending")),
+            )
+            .on(
+                &urls[0],
+                MockResponse::json(authorization_json("example.org", "deactivated")),
+            )
+            .on(
+                &urls[1],
+                MockResponse::json(authorization_json("www.example.org", "valid")),
+            )
+            .on(
+                &urls[1],
+                MockResponse::json(authorization_json("www.example.org", "deactivated")),
+            )
+            .on(
+                &urls[2],
+                MockResponse::json(authorization_json("example.org", "invalid")),
+            )
+            .on(
+                &urls[3],
+                MockResponse::json(authorization_json("example.com", "valid")),
+            );
+        let account = AccountMaterial::mock(ACCOUNT_URL);
+        let statuses = deactivate_authorizations(
+            &urls,
+            |it| it.identifier != Identifier::Dns("example.com".to_string()),
+            &account,
+            &directory(),
+            &client,
+        )
+        .await
+        .unwrap();
+        assert_eq!(
+            statuses,
+            vec![
+                AuthorizationStatus::Deactivated,
+                AuthorizationStatus::Deactivated,
+                AuthorizationStatus::Invalid,
+                AuthorizationStatus::Valid,
+            ]
+        );
+        // Only the pending and valid authorizations that match the filter are deactivated.
+        for (url, deactivated) in urls.iter().zip([true, true, false, false]) {
+            assert_eq!(
+                client
+                    .payloads(url)
+                    .contains(&json!({ "status": "deactivated" })),
+                deactivated
+            );
+        }
+    }
+
     #[test]
     fn test_order_deserialization() {
         let json = serde_json::to_string_pretty(&json!({
@@ -131,6 +515,10 @@ mod test {
         .unwrap();
         let deserialized = serde_json::from_str::<Authorization>(json.as_str()).unwrap();
         assert_eq!(deserialized.status, AuthorizationStatus::Pending);
+        assert_eq!(
+            deserialized.expires,
+            OffsetDateTime::from_unix_timestamp(1451743770).ok()
+        );
         assert_eq!(
             deserialized.identifier,
             Identifier::Dns("www.example.org".to_string())
@@ -174,6 +562,54 @@ mod test {
         );
     }
 
+    #[test(tokio::test)]
+    async fn test_wait_for_validations() {
+        let urls: Vec<String> = (0..2)
+            .map(|i| format!("https://example.com/acme/authz/{i}"))
+            .collect();
+        let account = AccountMaterial::mock(ACCOUNT_URL);
+        let client = MockClient::new()
+            .on(
+                &urls[0],
+                MockResponse::json(authorization_json("example.org", "valid")),
+            )
+            .on(
+                &urls[1],
+                MockResponse::json(authorization_json("www.example.org", "invalid")),
+            );
+        let pre_authorizations = wait_for_validations(
+            urls.clone(),
+            Instant::now(),
+            &account,
+            &directory(),
+            &client,
+        )
+        .await
+        .unwrap();
+        assert_eq!(
+            pre_authorizations
+                .into_iter()
+                .map(|it| it.status)
+                .collect::<Vec<_>>(),
+            vec![AuthorizationStatus::Valid, AuthorizationStatus::Invalid]
+        );
+        // Still pending at the deadline.
+        let client = MockClient::new()
+            .on(
+                &urls[0],
+                MockResponse::json(authorization_json("example.org", "valid")),
+            )
+            .on(
+                &urls[1],
+                MockResponse::json(authorization_json("www.example.org", "pending")),
+            );
+        let err = wait_for_validations(urls, Instant::now(), &account, &directory(), &client)
+            .await
+            .unwrap_err();
+        assert!(matches!(err.kind(), ErrorKind::Challenge));
+        assert!(err.to_string().contains("www.example.org"));
+    }
+
     #[cfg(feature = "reqwest")]
     #[test(tokio::test)]
     async fn test_authorize() {
@@ -195,6 +631,8 @@ mod test {
             Some("void.programingjd.me")
                 .iter()
                 .map(|&it| it.to_string()),
+            None,
+            &crate::order::OrderOptions::default(),
             &account,
             &directory,
             &acme.client,
@@ -0,0 +1,199 @@
+use crate::errors::{ErrorKind, Result};
+use base64::Engine;
+use base64::prelude::BASE64_URL_SAFE_NO_PAD;
+use rustls::pki_types::CertificateDer;
+use rustls::pki_types::pem::PemObject;
+use serde::{Deserialize, Serialize};
+use x509_parser::certificate::X509Certificate;
+use x509_parser::extensions::ParsedExtension;
+use x509_parser::prelude::FromDer;
+
+/// Preferred certificate chain, when the ACME server offers
+/// [alternate chains](https://datatracker.ietf.org/doc/html/rfc8555#section-7.4.2).
+/// The default chain is used when none of the chains match.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum PreferredChain {
+    /// The chain whose end-entity certificate is issued by the intermediate
+    /// with this common name (e.g. `R11`).
+    Issuer(String),
+    /// The chain that ends with a certificate issued by the root
+    /// with this common name (e.g. `ISRG Root X1`).
+    Root(String),
+}
+
+impl PreferredChain {
+    /// Check whether the chain matches, given the common names of its issuers.
+    pub(crate) fn matches(&self, issuers: &[String]) -> bool {
+        match self {
+            PreferredChain::Issuer(name) => issuers.first() == Some(name),
+            PreferredChain::Root(name) => issuers.last() == Some(name),
+        }
+    }
+}
+
+/// A certificate chain offered by the ACME server for an issued certificate.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CertificateChain {
+    /// The download url of the chain.
+    pub url: String,
+    /// The issuer common names of the certificates of the chain,
+    /// starting with the issuer of the end-entity certificate.
+    pub issuers: Vec<String>,
+}
+
+impl CertificateChain {
+    /// The common name of the intermediate that issued the end-entity certificate,
+    /// to select the chain with [PreferredChain::Issuer].
+    pub fn issuer(&self) -> Option<&str> {
+        self.issuers.first().map(String::as_str)
+    }
+    /// The common name of the root that the chain ends with,
+    /// to select the chain with [PreferredChain::Root].
+    pub fn root(&self) -> Option<&str> {
+        self.issuers.last().map(String::as_str)
+    }
+}
+
+/// The issuer common names of the certificates of a PEM chain,
+/// starting with the issuer of the end-entity certificate.
+pub(crate) fn chain_issuers(pem: &str) -> Result<Vec<String>> {
+    CertificateDer::pem_slice_iter(pem.as_bytes())
+        .map(|it| {
+            let der = it.map_err(|_| ErrorKind::InvalidCertificate.with_msg("invalid pem"))?;
+            let (_, certificate) = X509Certificate::from_der(der.as_ref()).map_err(|_| {
+                ErrorKind::InvalidCertificate.with_msg("failed to parse certificate")
+            })?;
+            Ok(certificate
+                .issuer()
+                .iter_common_name()
+                .next()
+                .and_then(|it| it.as_str().ok())
+                .unwrap_or_default()
+                .to_string())
+        })
+        .collect()
+}
+
+/// Get the DER of the first certificate of a PEM chain, or the DER itself.
+pub(crate) fn certificate_der(certificate: &[u8]) -> Result<CertificateDer<'static>> {
+    // A DER certificate starts with a SEQUENCE tag.
+    if certificate.first() == Some(&0x30) {
+        Ok(CertificateDer::from(certificate.to_vec()))
+    } else {
+        CertificateDer::from_pem_slice(certificate)
+            .map_err(|_| ErrorKind::InvalidCertificate.with_msg("no certificate found"))
+    }
+}
+
+/// [RFC 9773 Certificate Identifier](https://datatracker.ietf.org/doc/html/rfc9773#section-4.1)
+/// The base64url encoded key identifier of the authority key identifier extension,
+/// and the base64url encoded serial number, separated by a period.
+pub(crate) fn ari_identifier(certificate: &CertificateDer) -> Result<String> {
+    let (_, certificate) = X509Certificate::from_der(certificate.as_ref())
+        .map_err(|_| ErrorKind::InvalidCertificate.with_msg("failed to parse certificate"))?;
+    let key_identifier = certificate
+        .extensions()
+        .iter()
+        .find_map(|it| match it.parsed_extension() {
+            ParsedExtension::AuthorityKeyIdentifier(aki) => aki.key_identifier.as_ref(),
+            _ => None,
+        })
+        .ok_or(
+            ErrorKind::InvalidCertificate.with_msg("missing authority key identifier extension"),
+        )?;
+    Ok(format!(
+        "{}.{}",
+        BASE64_URL_SAFE_NO_PAD.encode(key_identifier.0),
+        BASE64_URL_SAFE_NO_PAD.encode(certificate.raw_serial())
+    ))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use rcgen::{CertificateParams, KeyIdMethod, KeyPair, SerialNumber};
+    use test_tracing::test;
+
+    #[test]
+    fn test_certificate_der() {
+        let cert = rcgen::generate_simple_self_signed(vec!["example.org".to_string()]).unwrap();
+        let der = cert.cert.der().to_vec();
+        assert_eq!(
+            certificate_der(cert.cert.pem().as_bytes())
+                .unwrap()
+                .as_ref(),
+            der.as_slice()
+        );
+        assert_eq!(certificate_der(&der).unwrap().as_ref(), der.as_slice());
+        let pem = [cert.signing_key.serialize_pem(), cert.cert.pem()].join("\n");
+        assert_eq!(
+            certificate_der(pem.as_bytes()).unwrap().as_ref(),
+            der.as_slice()
+        );
+        assert!(certificate_der(cert.signing_key.serialize_pem().as_bytes()).is_err());
+    }
+
+    #[test]
+    fn test_chain_selection() {
+        let root_key = KeyPair::generate().unwrap();
+        let mut root_params = CertificateParams::new(Vec::<String>::new()).unwrap();
+        root_params.is_ca = rcgen::IsCa::Ca(rcgen::BasicConstraints::Unconstrained);
+        root_params
+            .distinguished_name
+            .push(rcgen::DnType::CommonName, "Test Root");
+        let root = rcgen::Issuer::new(root_params, root_key);
+        let intermediate_key = KeyPair::generate().unwrap();
+        let mut intermediate_params = CertificateParams::new(Vec::<String>::new()).unwrap();
+        intermediate_params.is_ca = rcgen::IsCa::Ca(rcgen::BasicConstraints::Unconstrained);
+        intermediate_params
+            .distinguished_name
+            .push(rcgen::DnType::CommonName, "Test Intermediate");
+        let intermediate_cert = intermediate_params
+            .signed_by(&intermediate_key, &root)
+            .unwrap();
+        let intermediate = rcgen::Issuer::new(intermediate_params, intermediate_key);
+        let key = KeyPair::generate().unwrap();
+        let cert = CertificateParams::new(vec!["example.org".to_string()])
+            .unwrap()
+            .signed_by(&key, &intermediate)
+            .unwrap();
+        let chain = [cert.pem(), intermediate_cert.pem()].join("\n");
+        let issuers = chain_issuers(&chain).unwrap();
+        assert_eq!(issuers, vec!["Test Intermediate", "Test Root"]);
+        assert!(PreferredChain::Issuer("Test Intermediate".to_string()).matches(&issuers));
+        assert!(PreferredChain::Root("Test Root".to_string()).matches(&issuers));
+        assert!(!PreferredChain::Root("Test Intermediate".to_string()).matches(&issuers));
+        let issuers = chain_issuers(&cert.pem()).unwrap();
+        assert!(!PreferredChain::Root("Test Root".to_string()).matches(&issuers));
+        assert!(chain_issuers("not a certificate").unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_ari_identifier() {
+        // Example from RFC 9773 Section 4.1: the key identifier is
+        // 69:88:5B:6B:87:46:40:41:E1:B3:7B:84:7B:A0:AE:2C:DE:01:C8:D4
+        // and the serial number is 00:87:65:43:21.
+        let issuer_key = KeyPair::generate().unwrap();
+        let mut issuer_params =
+            CertificateParams::new(vec!["issuer.example.org".to_string()]).unwrap();
+        issuer_params.is_ca = rcgen::IsCa::Ca(rcgen::BasicConstraints::Unconstrained);
+        issuer_params.key_identifier_method = KeyIdMethod::PreSpecified(vec![
+            0x69, 0x88, 0x5B, 0x6B, 0x87, 0x46, 0x40, 0x41, 0xE1, 0xB3, 0x7B, 0x84, 0x7B, 0xA0,
+            0xAE, 0x2C, 0xDE, 0x01, 0xC8, 0xD4,
+        ]);
+        let issuer = rcgen::Issuer::new(issuer_params, issuer_key);
+        let key = KeyPair::generate().unwrap();
+        let mut params = CertificateParams::new(vec!["example.org".to_string()]).unwrap();
+        params.serial_number = Some(SerialNumber::from_slice(&[0x00, 0x87, 0x65, 0x43, 0x21]));
+        params.use_authority_key_identifier_extension = true;
+        let cert = params.signed_by(&key, &issuer).unwrap();
+        assert_eq!(
+            ari_identifier(cert.der()).unwrap(),
+            "aYhba4dGQEHhs3uEe6CuLN4ByNQ.AIdlQyE"
+        );
+        let self_signed =
+            rcgen::generate_simple_self_signed(vec!["example.org".to_string()]).unwrap();
+        assert!(ari_identifier(self_signed.cert.der()).is_err());
+    }
+}
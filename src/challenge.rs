@@ -2,7 +2,7 @@ use crate::account::AccountMaterial;
 use crate::client::{HttpClient, Response};
 use crate::directory::Directory;
 use crate::errors::{Error, ErrorKind, Result};
-use crate::jose::{jose, jwk};
+use crate::jose::jose;
 use rcgen::{CertificateParams, CustomExtension, KeyPair, PKCS_ECDSA_P256_SHA256};
 use ring::digest::{digest, SHA256};
 use rustls::crypto::ring::sign::any_supported_type;
@@ -10,8 +10,6 @@ use rustls::pki_types::PrivateKeyDer;
 use rustls::sign::CertifiedKey;
 use serde::Deserialize;
 use serde_json::json;
-#[cfg(feature = "tracing")]
-use tracing::debug;
 
 /// [RFC 8555 Challenge](https://datatracker.ietf.org/doc/html/rfc8555#section-8)
 #[derive(Deserialize, Clone, Debug, PartialEq, Eq)]
@@ -62,7 +60,7 @@ pub(crate) enum ChallengeStatus {
 impl Challenge {
     /// [RFC 8555 Key Authorizations](https://datatracker.ietf.org/doc/html/rfc8555#section-8.1)
     pub(crate) fn authorization_key(&self, account: &AccountMaterial) -> Vec<u8> {
-        let jwk = jwk(&account.keypair);
+        let jwk = account.keypair.jwk();
         let thumbprint = jwk.thumbprint();
         digest(
             &SHA256,
@@ -106,33 +104,28 @@ impl Challenge {
         directory: &Directory,
         client: &C,
     ) -> Result<Challenge> {
-        let nonce = directory.new_nonce(client).await?;
         let payload = json!({});
-        let body = jose(
-            &account.keypair,
-            Some(payload),
-            Some(&account.url),
-            Some(&nonce),
-            &self.url,
-        );
-        let response = client
-            .post_jose(&self.url, &body)
+        let response = directory
+            .signed_request(
+                &self.url,
+                async |nonce| {
+                    jose(
+                        &account.keypair,
+                        Some(payload.clone()),
+                        Some(&account.url),
+                        Some(nonce),
+                        &self.url,
+                    )
+                    .await
+                },
+                client,
+            )
             .await
             .map_err(|err| ErrorKind::Challenge.wrap(err))?;
-        if response.is_success() {
-            response
-                .body_as_json::<Challenge>()
-                .await
-                .map_err(|err| ErrorKind::Challenge.wrap(err))
-        } else {
-            #[cfg(feature = "tracing")]
-            if let Ok(text) = response.body_as_text().await {
-                debug!(body = ?text);
-            }
-            #[cfg(not(feature = "tracing"))]
-            let _ = response.body_as_text();
-            Err(ErrorKind::Challenge.into())
-        }
+        response
+            .body_as_json::<Challenge>()
+            .await
+            .map_err(|err| ErrorKind::Challenge.wrap(err))
     }
 }
 
@@ -141,6 +134,9 @@ mod test {
     use super::*;
     use serde_json::json;
     use test_tracing::test;
+    use x509_parser::certificate::X509Certificate;
+    use x509_parser::extensions::GeneralName;
+    use x509_parser::prelude::FromDer;
 
     #[test]
     fn test_order_deserialization() {
@@ -163,4 +159,15 @@ mod test {
             "LoqXcYV8q5ONbJQxbmR7SCTNo3tiAXDfowyjxAjEuX0"
         );
     }
+
+    #[test]
+    fn test_ip_challenge_certificate() {
+        let key = Challenge::certificate("192.0.2.1", &[0u8; 32]).unwrap();
+        let (_, cert) = X509Certificate::from_der(key.cert[0].as_ref()).unwrap();
+        let san = cert.subject_alternative_name().unwrap().unwrap();
+        assert_eq!(
+            san.value.general_names,
+            vec![GeneralName::IPAddress(&[192, 0, 2, 1])]
+        );
+    }
 }
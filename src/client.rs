@@ -1,6 +1,7 @@
 use crate::Acme;
 use crate::errors::Result;
-use crate::resolver::{CertResolver, DomainResolver, create_self_signed_certificate};
+use crate::order::Identifier;
+use crate::resolver::{CertResolver, create_self_signed_certificate};
 use rustls::sign::CertifiedKey;
 use serde::de::DeserializeOwned;
 use serde_json::Value;
@@ -8,10 +9,17 @@ use std::borrow::Borrow;
 use std::fmt::Debug;
 use std::marker::PhantomData;
 use std::sync::Arc;
+use std::time::Duration;
+use time::OffsetDateTime;
+use time::format_description::well_known::Rfc2822;
 
 #[allow(async_fn_in_trait)]
 pub trait HttpClient<R: Response>: Debug {
     async fn get_request(&self, url: impl AsRef<str>) -> Result<R>;
+    /// Defaults to a GET request, which is also accepted by the newNonce endpoint.
+    async fn head_request(&self, url: impl AsRef<str>) -> Result<R> {
+        self.get_request(url).await
+    }
     async fn post_jose(&self, url: impl AsRef<str>, body: impl Borrow<Value>) -> Result<R>;
 }
 
@@ -20,9 +28,61 @@ pub trait Response {
     fn status_code(&self) -> u16;
     fn is_success(&self) -> bool;
     fn header_value(&self, header_name: impl AsRef<str>) -> Option<String>;
+    /// All the values of a header that can be repeated (e.g. `Link`).
+    /// Defaults to the single value returned by [Response::header_value].
+    fn header_values(&self, header_name: impl AsRef<str>) -> Vec<String> {
+        self.header_value(header_name).into_iter().collect()
+    }
     async fn body_as_json<T: DeserializeOwned>(self) -> Result<T>;
     async fn body_as_text(self) -> Result<String>;
     async fn body_as_bytes(self) -> Result<impl Borrow<[u8]>>;
+    /// [RFC 8555 Retry-After](https://datatracker.ietf.org/doc/html/rfc8555#section-6.6)
+    /// The delay in seconds, or until the http date.
+    fn retry_after(&self) -> Option<Duration> {
+        self.header_value("retry-after")
+            .and_then(|it| parse_retry_after(&it, OffsetDateTime::now_utc()))
+    }
+    /// The target urls of the [RFC 8288 Link](https://datatracker.ietf.org/doc/html/rfc8288#section-3)
+    /// headers with the specified relation type.
+    fn links(&self, relation: &str) -> Vec<String> {
+        self.header_values("link")
+            .iter()
+            .flat_map(|it| parse_links(it, relation))
+            .collect()
+    }
+}
+
+/// [RFC 9110 Retry-After](https://datatracker.ietf.org/doc/html/rfc9110#section-10.2.3)
+/// Parse a `Retry-After` header value, either a delay in seconds or an http date
+/// (e.g. `Wed, 21 Oct 2015 07:28:00 GMT`). A date in the past is no delay.
+fn parse_retry_after(value: &str, now: OffsetDateTime) -> Option<Duration> {
+    let value = value.trim();
+    match value.parse::<u64>() {
+        Ok(seconds) => Some(Duration::from_secs(seconds)),
+        Err(_) => OffsetDateTime::parse(value, &Rfc2822)
+            .ok()
+            .map(|date| Duration::try_from(date - now).unwrap_or(Duration::ZERO)),
+    }
+}
+
+/// Parse a `Link` header value (e.g. `<https://example.com/cert/1>;rel="alternate"`),
+/// that can hold multiple comma separated links, and return the targets with the relation type.
+fn parse_links<'a>(value: &'a str, relation: &'a str) -> impl Iterator<Item = String> + 'a {
+    value.split(',').filter_map(move |link| {
+        let (target, params) = link.trim().strip_prefix('<')?.split_once('>')?;
+        params
+            .split(';')
+            .filter_map(|param| param.split_once('='))
+            .filter(|(name, _)| name.trim().eq_ignore_ascii_case("rel"))
+            .any(|(_, value)| {
+                value
+                    .trim()
+                    .trim_matches('"')
+                    .split_ascii_whitespace()
+                    .any(|it| it.eq_ignore_ascii_case(relation))
+            })
+            .then(|| target.trim().to_string())
+    })
 }
 
 impl<C: HttpClient<R> + Default, R: Response> Acme<R, C> {
@@ -35,13 +95,9 @@ impl<C: HttpClient<R> + Default, R: Response> Acme<R, C> {
         domain_names.for_each(|(domain, it)| {
             let domain = domain.into();
             domains.push(domain.clone());
-            resolver.map.pin().insert(
-                domain.clone(),
-                DomainResolver {
-                    key: Arc::new(it.unwrap_or_else(|| create_self_signed_certificate(&domain))),
-                    challenge_key: None,
-                    notifier: None,
-                },
+            resolver.install(
+                Some(&Identifier::from(domain.clone())).into_iter(),
+                it.unwrap_or_else(|| create_self_signed_certificate(&domain)),
             );
         });
         Self {
@@ -52,3 +108,62 @@ impl<C: HttpClient<R> + Default, R: Response> Acme<R, C> {
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::mock::MockResponse;
+    use test_tracing::test;
+
+    #[test]
+    fn test_parse_retry_after() {
+        let now = OffsetDateTime::from_unix_timestamp(1445412480).unwrap();
+        assert_eq!(
+            parse_retry_after("120", now),
+            Some(Duration::from_secs(120))
+        );
+        assert_eq!(parse_retry_after(" 0 ", now), Some(Duration::ZERO));
+        assert_eq!(
+            parse_retry_after("Wed, 21 Oct 2015 07:30:00 GMT", now),
+            Some(Duration::from_secs(120))
+        );
+        assert_eq!(
+            parse_retry_after("Wed, 21 Oct 2015 07:00:00 GMT", now),
+            Some(Duration::ZERO)
+        );
+        assert_eq!(parse_retry_after("soon", now), None);
+        assert_eq!(parse_retry_after("-1", now), None);
+        let date = (OffsetDateTime::now_utc() + time::Duration::HOUR)
+            .format(&Rfc2822)
+            .unwrap();
+        let retry_after = MockResponse::new(503)
+            .with_header("retry-after", date)
+            .retry_after()
+            .unwrap();
+        assert!(
+            retry_after > Duration::from_secs(3500) && retry_after <= Duration::from_secs(3600)
+        );
+    }
+
+    #[test]
+    fn test_parse_links() {
+        let value = r#"<https://example.com/acme/cert/1/1>;rel="alternate", <https://example.com/acme/directory>;rel="index""#;
+        assert_eq!(
+            parse_links(value, "alternate").collect::<Vec<_>>(),
+            vec!["https://example.com/acme/cert/1/1".to_string()]
+        );
+        assert_eq!(
+            parse_links(value, "index").collect::<Vec<_>>(),
+            vec!["https://example.com/acme/directory".to_string()]
+        );
+        assert_eq!(parse_links(value, "next").count(), 0);
+        assert_eq!(
+            parse_links("<https://example.com/next> ; rel=next", "next").collect::<Vec<_>>(),
+            vec!["https://example.com/next".to_string()]
+        );
+        assert_eq!(
+            parse_links("https://example.com;rel=next", "next").count(),
+            0
+        );
+    }
+}
@@ -1,11 +1,14 @@
 use crate::ecdsa::generate_pkcs8_ecdsa_keypair;
 use crate::errors::{Error, ErrorKind, Result};
 use rcgen::{CertificateParams, DistinguishedName, KeyPair};
+use serde::{Deserialize, Serialize};
 
 /// [RFC 8555 CSR](https://datatracker.ietf.org/doc/html/rfc8555#page-46)
-#[derive(Debug)]
+#[derive(Serialize, Deserialize, Debug)]
 pub struct Csr {
+    #[serde(rename = "privateKey")]
     pub(crate) private_key_pem: String,
+    #[serde(with = "crate::account::base64")]
     pub(crate) der: Vec<u8>,
 }
 
@@ -1,7 +1,10 @@
 use crate::client::{HttpClient, Response};
-use crate::errors::{Error, ErrorKind, Result};
+use crate::errors::{Error, ErrorKind, Problem, ProblemType, Result};
 use serde::Deserialize;
+use serde_json::Value;
+use std::collections::{BTreeMap, VecDeque};
 use std::fmt::Debug;
+use std::sync::Mutex;
 #[cfg(feature = "tracing")]
 use tracing::debug;
 
@@ -14,13 +17,55 @@ pub struct Directory {
     new_nonce: String,
     #[serde(rename = "newOrder")]
     pub(crate) new_order: String,
-    // #[serde(rename = "revokeCert")]
-    // revoke_cert: String,
+    /// [RFC 8555 Pre-Authorization](https://datatracker.ietf.org/doc/html/rfc8555#section-7.4.1)
+    #[serde(rename = "newAuthz")]
+    pub(crate) new_authz: Option<String>,
+    #[serde(rename = "revokeCert")]
+    pub(crate) revoke_cert: String,
     #[serde(rename = "keyChange")]
     pub(crate) key_change: String,
+    /// [RFC 9773 Renewal Information](https://datatracker.ietf.org/doc/html/rfc9773#section-4)
+    #[serde(rename = "renewalInfo")]
+    pub(crate) renewal_info: Option<String>,
+    #[serde(default)]
+    meta: DirectoryMeta,
+    /// Unused nonces returned by the ACME server in the `Replay-Nonce` header.
+    #[serde(skip)]
+    nonces: Mutex<VecDeque<String>>,
 }
 
+/// [RFC 8555 Directory Metadata](https://datatracker.ietf.org/doc/html/rfc8555#section-7.1.1)
+#[derive(Debug, Default, Deserialize)]
+pub struct DirectoryMeta {
+    /// The current terms of service.
+    #[serde(rename = "termsOfService")]
+    pub terms_of_service: Option<String>,
+    pub website: Option<String>,
+    /// The hostnames that the ACME server recognizes as referring to itself
+    /// for the purpose of [CAA record validation](https://datatracker.ietf.org/doc/html/rfc8659).
+    #[serde(rename = "caaIdentities", default)]
+    pub caa_identities: Vec<String>,
+    /// Whether an external account binding is required to create a new account.
+    #[serde(rename = "externalAccountRequired", default)]
+    pub external_account_required: bool,
+    /// [ACME Profiles](https://datatracker.ietf.org/doc/html/draft-aaron-acme-profiles)
+    /// The certificate profile names, with their description.
+    #[serde(default)]
+    pub profiles: BTreeMap<String, String>,
+}
+
+/// Maximum number of unused nonces that we keep around.
+const MAX_NONCES: usize = 16;
+
 impl Directory {
+    /// The optional metadata about the ACME server.
+    pub fn meta(&self) -> &DirectoryMeta {
+        &self.meta
+    }
+    /// Whether the ACME server supports pre-authorization.
+    pub fn supports_pre_authorization(&self) -> bool {
+        self.new_authz.is_some()
+    }
     /// [RFC 8555 Directory](https://datatracker.ietf.org/doc/html/rfc8555#section-7.1.1)
     #[cfg_attr(feature = "tracing", tracing::instrument(
         name = "get_directory",
@@ -53,7 +98,7 @@ impl Directory {
                 debug!(body = ?text);
             }
             #[cfg(not(feature = "tracing"))]
-            let _ = response.body_as_text();
+            let _ = response.body_as_text().await;
             Err(ErrorKind::FetchDirectory {
                 url: directory_url.to_string(),
             }
@@ -61,6 +106,8 @@ impl Directory {
         }
     }
     /// [RFC 8555 Nonce](https://datatracker.ietf.org/doc/html/rfc8555#section-7.2)
+    /// Use a nonce from a previous response if there is one,
+    /// otherwise get a new one from the newNonce endpoint.
     #[cfg_attr(feature = "tracing", tracing::instrument(
         name = "new_nonce",
         skip(client),
@@ -72,9 +119,12 @@ impl Directory {
         &self,
         client: &C,
     ) -> Result<String> {
+        if let Some(nonce) = self.nonces.lock().expect("poisoned nonces").pop_back() {
+            return Ok(nonce);
+        }
         let nounce_url = &self.new_nonce;
         let response = client
-            .get_request(nounce_url)
+            .head_request(nounce_url)
             .await
             .map_err(|err| ErrorKind::NewNonce.wrap(err))?;
         if response.is_success() {
@@ -89,19 +139,99 @@ impl Directory {
                 debug!(body = ?text);
             }
             #[cfg(not(feature = "tracing"))]
-            let _ = response.body_as_text();
+            let _ = response.body_as_text().await;
             Err(ErrorKind::NewNonce.into())
         }
     }
+    /// Keep the nonce returned by the ACME server for a later request.
+    fn store_nonce<R: Response>(&self, response: &R) {
+        if let Some(nonce) = response.header_value("replay-nonce") {
+            let mut nonces = self.nonces.lock().expect("poisoned nonces");
+            if nonces.len() == MAX_NONCES {
+                let _ = nonces.pop_front();
+            }
+            nonces.push_back(nonce);
+        }
+    }
+    /// [RFC 8555 Request Authentication](https://datatracker.ietf.org/doc/html/rfc8555#section-6.2)
+    /// Sign the request body with a nonce and post it.
+    /// If the ACME server rejects the nonce, the body is signed again
+    /// with the nonce from the error response and the request is retried once.
+    /// Unsuccessful responses are returned as errors, with the
+    /// [problem document](https://datatracker.ietf.org/doc/html/rfc8555#section-6.7) as the cause,
+    /// and rate limited requests fail with [ErrorKind::TooManyRequests].
+    pub(crate) async fn signed_request<C: HttpClient<R>, R: Response>(
+        &self,
+        url: impl AsRef<str>,
+        sign: impl AsyncFn(&str) -> Result<Value>,
+        client: &C,
+    ) -> Result<R> {
+        let url = url.as_ref();
+        let mut retried = false;
+        loop {
+            let nonce = self.new_nonce(client).await?;
+            let response = client.post_jose(url, sign(&nonce).await?).await?;
+            self.store_nonce(&response);
+            if response.is_success() {
+                return Ok(response);
+            }
+            let status = response.status_code();
+            let terms_of_service = response.links("terms-of-service").into_iter().next();
+            let text = response.body_as_text().await?;
+            #[cfg(feature = "tracing")]
+            debug!(body = ?text);
+            match serde_json::from_str::<Problem>(&text) {
+                Ok(problem) if problem.kind == ProblemType::BadNonce && !retried => {
+                    retried = true;
+                }
+                Ok(mut problem) => {
+                    let _ = problem.status.get_or_insert(status);
+                    problem.terms_of_service = terms_of_service;
+                    return Err(match status {
+                        429 => ErrorKind::TooManyRequests.with_problem(problem),
+                        _ => ErrorKind::Rejected { status }.with_problem(problem),
+                    });
+                }
+                Err(_) if status == 429 => return Err(ErrorKind::TooManyRequests.into()),
+                Err(_) => return Err(ErrorKind::Rejected { status }.with_msg(text)),
+            }
+        }
+    }
 }
 
 #[cfg(test)]
 mod test {
     use crate::directory::Directory;
+    use crate::errors::{ErrorKind, ProblemType};
+    use crate::jose::jose;
+    use crate::key::{KeyAlgorithm, MockSigner};
+    use crate::mock::{MockClient, MockResponse, directory};
     use rustls::crypto;
     use serde_json::json;
     use test_tracing::test;
 
+    const ACCOUNT_URL: &str = "https://example.com/acme/acct/evOfKhNU60wg";
+
+    /// Client that gets a userActionRequired problem for the account url.
+    fn user_action_required() -> MockClient {
+        MockClient::new().on(
+            ACCOUNT_URL,
+            MockResponse::new(403)
+                .with_header(
+                    "link",
+                    r#"<https://example.com/acme/terms/2017-6-02>;rel="terms-of-service""#,
+                )
+                .with_body(
+                    json!({
+                        "type": "urn:ietf:params:acme:error:userActionRequired",
+                        "detail": "Terms of service have changed",
+                        "instance": "https://example.com/acme/agreement/?token=W8Ih3PswD-8"
+                    })
+                    .to_string(),
+                ),
+        )
+    }
+
     #[test]
     fn test_deserialization() {
         let json = serde_json::to_string_pretty(&json!({
@@ -111,11 +241,16 @@ mod test {
             "newAuthz": "https://example.com/acme/new-authz",
             "revokeCert": "https://example.com/acme/revoke-cert",
             "keyChange": "https://example.com/acme/key-change",
+            "renewalInfo": "https://example.com/acme/renewal-info",
             "meta": {
                 "termsOfService": "https://example.com/acme/terms/2017-5-30",
                 "website": "https://www.example.com/",
                 "caaIdentities": ["example.com"],
-                "externalAccountRequired": false
+                "externalAccountRequired": false,
+                "profiles": {
+                    "classic": "https://example.com/acme/profiles#classic",
+                    "shortlived": "https://example.com/acme/profiles#shortlived"
+                }
             }
         }))
         .unwrap();
@@ -126,10 +261,32 @@ mod test {
             "https://example.com/acme/new-account"
         );
         assert_eq!(deserialized.new_order, "https://example.com/acme/new-order");
+        assert!(deserialized.supports_pre_authorization());
+        assert_eq!(
+            deserialized.revoke_cert,
+            "https://example.com/acme/revoke-cert"
+        );
         assert_eq!(
             deserialized.key_change,
             "https://example.com/acme/key-change"
         );
+        assert_eq!(
+            deserialized.renewal_info.as_deref(),
+            Some("https://example.com/acme/renewal-info")
+        );
+        let meta = deserialized.meta();
+        assert_eq!(
+            meta.terms_of_service.as_deref(),
+            Some("https://example.com/acme/terms/2017-5-30")
+        );
+        assert_eq!(meta.website.as_deref(), Some("https://www.example.com/"));
+        assert_eq!(meta.caa_identities, vec!["example.com".to_string()]);
+        assert!(!meta.external_account_required);
+        assert_eq!(meta.profiles.len(), 2);
+        assert_eq!(
+            meta.profiles.get("shortlived").map(|it| it.as_str()),
+            Some("https://example.com/acme/profiles#shortlived")
+        );
     }
 
     #[cfg(feature = "reqwest")]
@@ -161,6 +318,10 @@ mod test {
             directory.new_order,
             format!("https://{}/acme/new-order", environment.domain())
         );
+        assert_eq!(
+            directory.revoke_cert,
+            format!("https://{}/acme/revoke-cert", environment.domain())
+        );
         assert_eq!(
             directory.key_change,
             format!("https://{}/acme/key-change", environment.domain())
@@ -188,6 +349,117 @@ mod test {
             .await
             .unwrap();
         let nonce = directory.new_nonce(&acme.client).await.unwrap();
-        assert!(nonce.len() > 0)
+        assert!(!nonce.is_empty())
+    }
+
+    #[test(tokio::test)]
+    async fn test_nonce_pool() {
+        let directory = directory();
+        let client = MockClient::default();
+        directory.store_nonce(&MockResponse::new(200).with_header("replay-nonce", "first"));
+        directory.store_nonce(&MockResponse::new(200).with_header("replay-nonce", "second"));
+        assert_eq!(directory.new_nonce(&client).await.unwrap(), "second");
+        assert_eq!(directory.new_nonce(&client).await.unwrap(), "first");
+        assert!(directory.new_nonce(&client).await.is_err());
+    }
+
+    #[test(tokio::test)]
+    async fn test_bad_nonce_retry() {
+        let directory = directory();
+        let client = MockClient::new().on(
+            ACCOUNT_URL,
+            MockResponse::problem(400, "badNonce", "JWS has an invalid anti-replay nonce"),
+        );
+        let client = client.on(
+            ACCOUNT_URL,
+            MockResponse::json(json!({ "status": "valid" })),
+        );
+        assert!(
+            directory
+                .signed_request(ACCOUNT_URL, async |_| Ok(json!({})), &client)
+                .await
+                .is_ok()
+        );
+        assert_eq!(client.payloads(ACCOUNT_URL).len(), 2);
+    }
+
+    #[test(tokio::test)]
+    async fn test_rate_limited_nonce() {
+        let directory = directory();
+        let client = MockClient::new().on(
+            ACCOUNT_URL,
+            MockResponse::problem(429, "rateLimited", "too many new orders recently")
+                .with_header("replay-nonce", "rate-limited-nonce"),
+        );
+        let err = directory
+            .signed_request(ACCOUNT_URL, async |_| Ok(json!({})), &client)
+            .await
+            .err()
+            .unwrap();
+        assert!(matches!(err.kind(), ErrorKind::TooManyRequests));
+        assert_eq!(err.problem().unwrap().kind, ProblemType::RateLimited);
+        assert_eq!(err.problem().unwrap().status, Some(429));
+        // The nonce of the rate limited response is used for the next request.
+        assert_eq!(
+            directory.new_nonce(&client).await.unwrap(),
+            "rate-limited-nonce"
+        );
+    }
+
+    #[test(tokio::test)]
+    async fn test_terms_of_service_link() {
+        let directory = directory();
+        let err = directory
+            .signed_request(
+                ACCOUNT_URL,
+                async |_| Ok(json!({})),
+                &user_action_required(),
+            )
+            .await
+            .err()
+            .unwrap();
+        assert!(matches!(err.kind(), ErrorKind::Rejected { status: 403 }));
+        let problem = err.problem().unwrap();
+        assert_eq!(problem.kind, ProblemType::UserActionRequired);
+        assert_eq!(
+            problem.terms_of_service.as_deref(),
+            Some("https://example.com/acme/terms/2017-6-02")
+        );
+        assert_eq!(
+            problem.instance.as_deref(),
+            Some("https://example.com/acme/agreement/?token=W8Ih3PswD-8")
+        );
+    }
+
+    #[test(tokio::test)]
+    async fn test_signed_request_signatures() {
+        let directory = directory();
+        let client = MockClient::new()
+            .on(
+                ACCOUNT_URL,
+                MockResponse::problem(400, "badNonce", "JWS has an invalid anti-replay nonce"),
+            )
+            .on(
+                ACCOUNT_URL,
+                MockResponse::json(json!({ "status": "valid" })),
+            );
+        let signer = MockSigner::new(KeyAlgorithm::EdDsa).unwrap();
+        let sign = async |nonce: &str| jose(&signer, None, None, Some(nonce), ACCOUNT_URL).await;
+        // The request is signed again with the new nonce after a badNonce error.
+        directory
+            .signed_request(ACCOUNT_URL, sign, &client)
+            .await
+            .unwrap();
+        assert_eq!(signer.signatures(), 2);
+        assert_eq!(client.requests().len(), 2);
+        // Nothing is sent if the request can't be signed.
+        signer.set_unavailable(true);
+        let err = directory
+            .signed_request(ACCOUNT_URL, sign, &client)
+            .await
+            .err()
+            .unwrap();
+        assert!(matches!(err.kind(), ErrorKind::Signature));
+        assert_eq!(client.requests().len(), 2);
     }
 }
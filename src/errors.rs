@@ -1,4 +1,5 @@
-use crate::csr::Csr;
+use crate::order::{Identifier, PendingOrder};
+use serde::{Deserialize, Deserializer};
 use std::fmt::{Debug, Display, Formatter};
 
 pub type Result<T> = std::result::Result<T, Error>;
@@ -13,31 +14,52 @@ pub struct Error {
 pub enum ErrorDetail {
     Error(Box<Error>),
     Message(String),
+    Problem(Box<Problem>),
 }
 
 #[derive(Debug)]
 pub enum ErrorKind {
     ConnectionError,
+    Rejected { status: u16 },
     TooManyRequests,
     ServiceUnavailable,
     DeserializationError { type_name: String },
     FetchDirectory { url: String },
     InvalidKey,
+    UnsupportedKey { key_type: String },
+    Signature,
     NewNonce,
     NewAccount,
+    ExternalAccountRequired,
+    InvalidExternalAccountKey,
+    InvalidContact { contact: String },
+    UserActionRequired { terms_of_service: Option<String> },
+    TermsOfServiceAgreement,
     DeserializeAccount,
+    ImportAccount,
     GetAccount,
     ChangeAccountKey,
+    DeactivateAccount,
     Csr { domains: Vec<String> },
     NewOrder,
+    UnsupportedProfile { profile: String },
+    OrderValidity,
     InvalidOrder { domains: Vec<String> },
     GetAuthorization,
+    NewAuthorization,
     InvalidAuthorization,
+    DeactivateAuthorization,
     GetOrder,
+    ListOrders,
     Challenge,
     FinalizeOrder,
     DownloadCertificate,
-    OrderProcessing { csr: Csr },
+    InstallCertificate,
+    InvalidCertificate,
+    RenewalInfo,
+    RevokeCertificate,
+    OrderProcessing { order: Box<PendingOrder> },
+    OrderTimeout { status: String },
 }
 
 impl From<ErrorKind> for Error {
@@ -62,6 +84,26 @@ impl ErrorKind {
             cause: Some(ErrorDetail::Message(msg.into())),
         }
     }
+    pub fn with_problem(self, problem: Problem) -> Error {
+        Error {
+            kind: self,
+            cause: Some(ErrorDetail::Problem(Box::new(problem))),
+        }
+    }
+}
+
+impl Error {
+    pub fn kind(&self) -> &ErrorKind {
+        &self.kind
+    }
+    /// The problem document returned by the ACME server, if there is one in the chain of causes.
+    pub fn problem(&self) -> Option<&Problem> {
+        match &self.cause {
+            Some(ErrorDetail::Problem(problem)) => Some(problem),
+            Some(ErrorDetail::Error(err)) => err.problem(),
+            _ => None,
+        }
+    }
 }
 
 impl Display for Error {
@@ -83,6 +125,7 @@ impl Display for ErrorDetail {
         match self {
             ErrorDetail::Error(err) => write!(f, "{err}"),
             ErrorDetail::Message(msg) => f.write_str(msg),
+            ErrorDetail::Problem(problem) => write!(f, "{problem}"),
         }
     }
 }
@@ -93,6 +136,9 @@ impl Display for ErrorKind {
             ErrorKind::ConnectionError => {
                 write!(f, "could not connect to acme server")
             }
+            ErrorKind::Rejected { status } => {
+                write!(f, "request rejected by acme server with status {status}")
+            }
             ErrorKind::TooManyRequests => {
                 write!(f, "too many requests to acme server")
             }
@@ -108,24 +154,61 @@ impl Display for ErrorKind {
             ErrorKind::InvalidKey => {
                 write!(
                     f,
-                    "invalid pkcs8 (the key should be ECDSA_P256_SHA256_FIXED_SIGNING)"
+                    "invalid pkcs8 (the key should be ECDSA P-256 or P-384, RSA or Ed25519)"
                 )
             }
+            ErrorKind::UnsupportedKey { key_type } => {
+                write!(
+                    f,
+                    "unsupported key type \"{key_type}\" (the key should be ECDSA P-256 or P-384, RSA or Ed25519)"
+                )
+            }
+            ErrorKind::Signature => {
+                write!(f, "could not sign the request")
+            }
             ErrorKind::NewNonce => {
                 write!(f, "could not get a new nonce")
             }
             ErrorKind::NewAccount => {
                 write!(f, "could not create account")
             }
+            ErrorKind::UserActionRequired { terms_of_service } => match terms_of_service {
+                Some(terms_of_service) => write!(
+                    f,
+                    "the terms of service need to be agreed to: {terms_of_service}"
+                ),
+                None => write!(f, "user action required"),
+            },
+            ErrorKind::TermsOfServiceAgreement => {
+                write!(f, "failed to agree to the terms of service")
+            }
+            ErrorKind::ExternalAccountRequired => {
+                write!(
+                    f,
+                    "the acme server requires an external account binding to create an account"
+                )
+            }
+            ErrorKind::InvalidExternalAccountKey => {
+                write!(f, "invalid external account binding mac key")
+            }
+            ErrorKind::InvalidContact { contact } => {
+                write!(f, "invalid account contact {contact}")
+            }
             ErrorKind::DeserializeAccount => {
                 write!(f, "could not deserialize account")
             }
+            ErrorKind::ImportAccount => {
+                write!(f, "could not import account")
+            }
             ErrorKind::GetAccount => {
                 write!(f, "could not get account")
             }
             ErrorKind::ChangeAccountKey => {
                 write!(f, "could not change account key")
             }
+            ErrorKind::DeactivateAccount => {
+                write!(f, "could not deactivate account")
+            }
             ErrorKind::Csr { domains } => {
                 write!(
                     f,
@@ -151,27 +234,303 @@ impl Display for ErrorKind {
                         .join(", ")
                 )
             }
+            ErrorKind::UnsupportedProfile { profile } => {
+                write!(
+                    f,
+                    "the acme server doesn't support the certificate profile \"{profile}\""
+                )
+            }
+            ErrorKind::OrderValidity => {
+                write!(f, "unsupported certificate validity period")
+            }
             ErrorKind::GetAuthorization => {
                 write!(f, "could not get authorization challenges")
             }
+            ErrorKind::NewAuthorization => {
+                write!(f, "could not create new authorization")
+            }
+            ErrorKind::DeactivateAuthorization => {
+                write!(f, "could not deactivate authorization")
+            }
             ErrorKind::InvalidAuthorization => {
                 write!(f, "invalid authorization")
             }
             ErrorKind::GetOrder => {
                 write!(f, "could not get order")
             }
+            ErrorKind::ListOrders => {
+                write!(f, "could not list account orders")
+            }
             ErrorKind::Challenge => {
                 write!(f, "could not validate challenge")
             }
             ErrorKind::DownloadCertificate => {
                 write!(f, "failed to download certificate")
             }
+            ErrorKind::InstallCertificate => {
+                write!(f, "failed to install certificate")
+            }
+            ErrorKind::InvalidCertificate => {
+                write!(f, "invalid certificate")
+            }
+            ErrorKind::RenewalInfo => {
+                write!(f, "could not get certificate renewal info")
+            }
+            ErrorKind::RevokeCertificate => {
+                write!(f, "failed to revoke certificate")
+            }
             ErrorKind::FinalizeOrder => {
                 write!(f, "failed to finalize order")
             }
             ErrorKind::OrderProcessing { .. } => {
                 write!(f, "order processing stalled")
             }
+            ErrorKind::OrderTimeout { status } => {
+                write!(f, "timed out waiting for order (order is {status})")
+            }
+        }
+    }
+}
+
+/// [RFC 7807 Problem Details](https://datatracker.ietf.org/doc/html/rfc7807)
+/// returned by the ACME server when a request fails.
+#[derive(Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Problem {
+    #[serde(rename = "type", default)]
+    pub kind: ProblemType,
+    pub detail: Option<String>,
+    /// The http status code of the response if the problem document doesn't include it.
+    pub status: Option<u16>,
+    pub instance: Option<String>,
+    /// [RFC 8555 Subproblems](https://datatracker.ietf.org/doc/html/rfc8555#section-6.7.1)
+    #[serde(default)]
+    pub subproblems: Vec<Subproblem>,
+    /// The new terms of service url, from the `Link: rel="terms-of-service"` header
+    /// of the response, when the ACME server requires
+    /// [agreeing to updated terms](https://datatracker.ietf.org/doc/html/rfc8555#section-7.3.3).
+    #[serde(skip)]
+    pub terms_of_service: Option<String>,
+}
+
+/// [RFC 8555 Subproblems](https://datatracker.ietf.org/doc/html/rfc8555#section-6.7.1)
+#[derive(Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Subproblem {
+    #[serde(rename = "type", default)]
+    pub kind: ProblemType,
+    pub detail: Option<String>,
+    pub identifier: Option<Identifier>,
+}
+
+/// [RFC 8555 Error Types](https://datatracker.ietf.org/doc/html/rfc8555#section-6.7)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ProblemType {
+    AccountDoesNotExist,
+    AlreadyRevoked,
+    BadCsr,
+    BadNonce,
+    BadPublicKey,
+    BadRevocationReason,
+    BadSignatureAlgorithm,
+    Caa,
+    Compound,
+    Connection,
+    Dns,
+    ExternalAccountRequired,
+    IncorrectResponse,
+    InvalidContact,
+    Malformed,
+    OrderNotReady,
+    RateLimited,
+    RejectedIdentifier,
+    ServerInternal,
+    Tls,
+    Unauthorized,
+    UnsupportedContact,
+    UnsupportedIdentifier,
+    UserActionRequired,
+    /// Any other problem type uri.
+    Other(String),
+}
+
+const ACME_ERROR_PREFIX: &str = "urn:ietf:params:acme:error:";
+
+impl ProblemType {
+    fn acme_error(&self) -> Option<&'static str> {
+        match self {
+            ProblemType::AccountDoesNotExist => Some("accountDoesNotExist"),
+            ProblemType::AlreadyRevoked => Some("alreadyRevoked"),
+            ProblemType::BadCsr => Some("badCSR"),
+            ProblemType::BadNonce => Some("badNonce"),
+            ProblemType::BadPublicKey => Some("badPublicKey"),
+            ProblemType::BadRevocationReason => Some("badRevocationReason"),
+            ProblemType::BadSignatureAlgorithm => Some("badSignatureAlgorithm"),
+            ProblemType::Caa => Some("caa"),
+            ProblemType::Compound => Some("compound"),
+            ProblemType::Connection => Some("connection"),
+            ProblemType::Dns => Some("dns"),
+            ProblemType::ExternalAccountRequired => Some("externalAccountRequired"),
+            ProblemType::IncorrectResponse => Some("incorrectResponse"),
+            ProblemType::InvalidContact => Some("invalidContact"),
+            ProblemType::Malformed => Some("malformed"),
+            ProblemType::OrderNotReady => Some("orderNotReady"),
+            ProblemType::RateLimited => Some("rateLimited"),
+            ProblemType::RejectedIdentifier => Some("rejectedIdentifier"),
+            ProblemType::ServerInternal => Some("serverInternal"),
+            ProblemType::Tls => Some("tls"),
+            ProblemType::Unauthorized => Some("unauthorized"),
+            ProblemType::UnsupportedContact => Some("unsupportedContact"),
+            ProblemType::UnsupportedIdentifier => Some("unsupportedIdentifier"),
+            ProblemType::UserActionRequired => Some("userActionRequired"),
+            ProblemType::Other(_) => None,
+        }
+    }
+}
+
+impl Default for ProblemType {
+    fn default() -> Self {
+        ProblemType::Other("about:blank".to_string())
+    }
+}
+
+impl From<String> for ProblemType {
+    fn from(value: String) -> Self {
+        match value.strip_prefix(ACME_ERROR_PREFIX) {
+            Some("accountDoesNotExist") => ProblemType::AccountDoesNotExist,
+            Some("alreadyRevoked") => ProblemType::AlreadyRevoked,
+            Some("badCSR") => ProblemType::BadCsr,
+            Some("badNonce") => ProblemType::BadNonce,
+            Some("badPublicKey") => ProblemType::BadPublicKey,
+            Some("badRevocationReason") => ProblemType::BadRevocationReason,
+            Some("badSignatureAlgorithm") => ProblemType::BadSignatureAlgorithm,
+            Some("caa") => ProblemType::Caa,
+            Some("compound") => ProblemType::Compound,
+            Some("connection") => ProblemType::Connection,
+            Some("dns") => ProblemType::Dns,
+            Some("externalAccountRequired") => ProblemType::ExternalAccountRequired,
+            Some("incorrectResponse") => ProblemType::IncorrectResponse,
+            Some("invalidContact") => ProblemType::InvalidContact,
+            Some("malformed") => ProblemType::Malformed,
+            Some("orderNotReady") => ProblemType::OrderNotReady,
+            Some("rateLimited") => ProblemType::RateLimited,
+            Some("rejectedIdentifier") => ProblemType::RejectedIdentifier,
+            Some("serverInternal") => ProblemType::ServerInternal,
+            Some("tls") => ProblemType::Tls,
+            Some("unauthorized") => ProblemType::Unauthorized,
+            Some("unsupportedContact") => ProblemType::UnsupportedContact,
+            Some("unsupportedIdentifier") => ProblemType::UnsupportedIdentifier,
+            Some("userActionRequired") => ProblemType::UserActionRequired,
+            _ => ProblemType::Other(value),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for ProblemType {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        String::deserialize(deserializer).map(ProblemType::from)
+    }
+}
+
+impl Display for ProblemType {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ProblemType::Other(uri) => f.write_str(uri),
+            _ => f.write_str(self.acme_error().unwrap_or_default()),
         }
     }
 }
+
+impl Display for Problem {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match &self.detail {
+            Some(detail) => write!(f, "{}: {detail}", self.kind)?,
+            None => write!(f, "{}", self.kind)?,
+        }
+        for subproblem in &self.subproblems {
+            write!(f, "\n{subproblem}")?;
+        }
+        Ok(())
+    }
+}
+
+impl Display for Subproblem {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        if let Some(identifier) = &self.identifier {
+            write!(f, "{identifier}: ")?;
+        }
+        match &self.detail {
+            Some(detail) => write!(f, "{}: {detail}", self.kind),
+            None => write!(f, "{}", self.kind),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde_json::json;
+    use test_tracing::test;
+
+    #[test]
+    fn test_problem_deserialization() {
+        let json = serde_json::to_string_pretty(&json!({
+            "type": "urn:ietf:params:acme:error:malformed",
+            "detail": "Some of the identifiers requested were rejected",
+            "subproblems": [
+                {
+                    "type": "urn:ietf:params:acme:error:malformed",
+                    "detail": "Invalid underscore in DNS name \"_example.org\"",
+                    "identifier": {
+                        "type": "dns",
+                        "value": "_example.org"
+                    }
+                },
+                {
+                    "type": "urn:ietf:params:acme:error:rejectedIdentifier",
+                    "detail": "This CA will not issue for \"example.net\"",
+                    "identifier": {
+                        "type": "dns",
+                        "value": "example.net"
+                    }
+                }
+            ]
+        }))
+        .unwrap();
+        let deserialized = serde_json::from_str::<Problem>(json.as_str()).unwrap();
+        assert_eq!(deserialized.kind, ProblemType::Malformed);
+        assert_eq!(deserialized.status, None);
+        assert_eq!(deserialized.subproblems.len(), 2);
+        assert_eq!(
+            deserialized.subproblems[1].kind,
+            ProblemType::RejectedIdentifier
+        );
+        assert_eq!(
+            deserialized.subproblems[1].identifier,
+            Some(Identifier::Dns("example.net".to_string()))
+        );
+        assert_eq!(
+            deserialized.to_string(),
+            "malformed: Some of the identifiers requested were rejected\n\
+            _example.org: malformed: Invalid underscore in DNS name \"_example.org\"\n\
+            example.net: rejectedIdentifier: This CA will not issue for \"example.net\""
+        );
+    }
+
+    #[test]
+    fn test_problem_in_cause() {
+        let problem = serde_json::from_value::<Problem>(json!({
+            "type": "urn:ietf:params:acme:error:rateLimited",
+            "detail": "too many certificates for example.com",
+            "status": 429
+        }))
+        .unwrap();
+        let err = ErrorKind::NewOrder.wrap(ErrorKind::TooManyRequests.with_problem(problem));
+        assert_eq!(err.problem().unwrap().kind, ProblemType::RateLimited);
+        assert_eq!(err.problem().unwrap().status, Some(429));
+        assert_eq!(
+            err.to_string(),
+            "could not get or create new order:\n\
+            too many requests to acme server:\n\
+            rateLimited: too many certificates for example.com"
+        );
+    }
+}
@@ -1,30 +1,28 @@
+use crate::errors::Result;
+use crate::key::{KeyAlgorithm, PublicKey, Signer};
 use base64::Engine;
 use base64::prelude::BASE64_URL_SAFE_NO_PAD;
 use ring::digest::{SHA256, digest};
-use ring::rand::SystemRandom;
-use ring::signature::{EcdsaKeyPair, KeyPair};
+use ring::hmac;
 use serde::Serialize;
 use serde_json::Value;
 
-const ALGORITHM: &str = "ES256";
-const CURVE: &str = "P-256";
-const KEY_TYPE: &str = "EC";
 const PUBLIC_KEY_USE: &str = "sig";
 
 /// [RFC 8555 Request Authentication](https://datatracker.ietf.org/doc/html/rfc8555#section-6.2)
-pub(crate) fn jose(
-    keypair: &EcdsaKeyPair,
+pub(crate) async fn jose(
+    keypair: &dyn Signer,
     payload: Option<Value>,
     kid: Option<&str>,
     nonce: Option<&str>,
     url: &str,
-) -> Value {
+) -> Result<Value> {
     let jwk = match kid {
-        None => Some(jwk(keypair)),
+        None => Some(keypair.jwk()),
         _ => None,
     };
     let protected = Protected {
-        alg: "ES256",
+        alg: keypair.algorithm().name(),
         jwk,
         kid,
         nonce,
@@ -37,50 +35,97 @@ pub(crate) fn jose(
         None => String::new(),
     };
     let message = format!("{protected}.{payload}");
-    let signature = keypair
-        .sign(&SystemRandom::new(), message.as_bytes())
-        .expect("failed to sign message");
-    let signature = BASE64_URL_SAFE_NO_PAD.encode(signature.as_ref());
+    let signature = BASE64_URL_SAFE_NO_PAD.encode(keypair.sign(message.as_bytes()).await?);
     let body = Body {
         protected,
         payload,
         signature,
     };
-    serde_json::to_value(body).expect("failed to serialize jose")
+    Ok(serde_json::to_value(body).expect("failed to serialize jose"))
 }
 
-pub(crate) fn jwk(keypair: &EcdsaKeyPair) -> Jwk {
-    let (x, y) = keypair.public_key().as_ref()[1..].split_at(32);
-    Jwk {
-        alg: ALGORITHM,
-        crv: CURVE,
-        kty: KEY_TYPE,
-        u: PUBLIC_KEY_USE,
-        x: base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(x),
-        y: BASE64_URL_SAFE_NO_PAD.encode(y),
-    }
+/// [RFC 8555 External Account Binding](https://datatracker.ietf.org/doc/html/rfc8555#section-7.3.4)
+/// The account public key signed with the MAC key provided by the CA.
+pub(crate) fn eab_jose(key_id: &str, hmac_key: &[u8], keypair: &dyn Signer, url: &str) -> Value {
+    let protected = EabProtected {
+        alg: "HS256",
+        kid: key_id,
+        url,
+    };
+    let protected = BASE64_URL_SAFE_NO_PAD
+        .encode(serde_json::to_vec(&protected).expect("failed to serialize jose"));
+    let payload = BASE64_URL_SAFE_NO_PAD
+        .encode(serde_json::to_vec(&keypair.jwk()).expect("failed to serialize jwk"));
+    let message = format!("{protected}.{payload}");
+    let key = hmac::Key::new(hmac::HMAC_SHA256, hmac_key);
+    let signature = BASE64_URL_SAFE_NO_PAD.encode(hmac::sign(&key, message.as_bytes()).as_ref());
+    let body = Body {
+        protected,
+        payload,
+        signature,
+    };
+    serde_json::to_value(body).expect("failed to serialize jose")
 }
 
-#[derive(Serialize)]
-pub(crate) struct Jwk {
+/// [RFC 7517 JSON Web Key](https://datatracker.ietf.org/doc/html/rfc7517#section-4)
+/// with the key type specific members from
+/// [RFC 7518](https://datatracker.ietf.org/doc/html/rfc7518#section-6)
+/// and [RFC 8037](https://datatracker.ietf.org/doc/html/rfc8037#section-2).
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct Jwk {
     alg: &'static str,
-    crv: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    crv: Option<&'static str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    e: Option<String>,
     kty: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    n: Option<String>,
     #[serde(rename = "use")]
     u: &'static str,
-    x: String,
-    y: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    x: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    y: Option<String>,
 }
 
 impl Jwk {
-    pub(crate) fn thumbprint(&self) -> String {
+    pub(crate) fn new(algorithm: KeyAlgorithm, public_key: PublicKey) -> Self {
+        let (crv, kty, e, n, x, y) = match public_key {
+            PublicKey::Ec { x, y } => {
+                let crv = match algorithm {
+                    KeyAlgorithm::Es384 => "P-384",
+                    _ => "P-256",
+                };
+                (Some(crv), "EC", None, None, Some(x), Some(y))
+            }
+            PublicKey::Rsa { n, e } => (None, "RSA", Some(e), Some(n), None, None),
+            PublicKey::Okp { x } => (Some("Ed25519"), "OKP", None, None, Some(x), None),
+        };
+        let encode = |it: Vec<u8>| BASE64_URL_SAFE_NO_PAD.encode(it);
+        Jwk {
+            alg: algorithm.name(),
+            crv,
+            e: e.map(encode),
+            kty,
+            n: n.map(encode),
+            u: PUBLIC_KEY_USE,
+            x: x.map(encode),
+            y: y.map(encode),
+        }
+    }
+    /// [RFC 7638 JWK Thumbprint](https://datatracker.ietf.org/doc/html/rfc7638#section-3.2)
+    /// Only the required members, in lexicographic order.
+    pub fn thumbprint(&self) -> String {
         BASE64_URL_SAFE_NO_PAD.encode(digest(
             &SHA256,
             &serde_json::to_vec(&JwkThumb {
                 crv: self.crv,
+                e: self.e.as_deref(),
                 kty: self.kty,
-                x: &self.x,
-                y: &self.y,
+                n: self.n.as_deref(),
+                x: self.x.as_deref(),
+                y: self.y.as_deref(),
             })
             .expect("failed to serialize JwkThumb"),
         ))
@@ -89,10 +134,17 @@ impl Jwk {
 
 #[derive(Serialize)]
 struct JwkThumb<'a> {
-    crv: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    crv: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    e: Option<&'a str>,
     kty: &'a str,
-    x: &'a str,
-    y: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    n: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    x: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    y: Option<&'a str>,
 }
 
 #[derive(Serialize)]
@@ -107,9 +159,80 @@ struct Protected<'a> {
     url: &'a str,
 }
 
+#[derive(Serialize)]
+struct EabProtected<'a> {
+    alg: &'static str,
+    kid: &'a str,
+    url: &'a str,
+}
+
 #[derive(Serialize)]
 struct Body {
     protected: String,
     payload: String,
     signature: String,
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::key::{AccountKey, generate_pkcs8_keypair};
+    use test_tracing::test;
+
+    #[test]
+    fn test_rsa_thumbprint() {
+        // https://datatracker.ietf.org/doc/html/rfc7638#section-3.1
+        let jwk = Jwk {
+            alg: "RS256",
+            crv: None,
+            e: Some("AQAB".to_string()),
+            kty: "RSA",
+            n: Some(
+                "0vx7agoebGcQSuuPiLJXZptN9nndrQmbXEps2aiAFbWhM78LhWx4cbbfAAtVT86zwu1RK7aPFFxuhDR1L6tSoc_BJECPebWKRXjBZCiFV4n3oknjhMstn64tZ_2W-5JsGY4Hc5n9yBXArwl93lqt7_RN5w6Cf0h4QyQ5v-65YGjQR0_FDW2QvzqY368QQMicAtaSqzs8KJZgnYb9c7d0zgdAZHzu6qMQvRL5hajrn1n91CbOpbISD08qNLyrdkt-bFTWhAI4vMQFh6WeZu0fM4lFd2NcRwr3XPksINHaQ-G_xBniIqbw0Ls1jF44-csFCur-kEgU8awapJzKnqDKgw".to_string(),
+            ),
+            u: PUBLIC_KEY_USE,
+            x: None,
+            y: None,
+        };
+        assert_eq!(
+            jwk.thumbprint(),
+            "NzbLsXh8uDCcd-6MNwXF4W_7noWXFZAfHkxZsRGC9Xs"
+        );
+    }
+
+    #[test(tokio::test)]
+    async fn test_jwk_members() {
+        for (algorithm, expected) in [
+            (
+                KeyAlgorithm::Es256,
+                serde_json::json!({"alg": "ES256", "crv": "P-256", "kty": "EC"}),
+            ),
+            (
+                KeyAlgorithm::Es384,
+                serde_json::json!({"alg": "ES384", "crv": "P-384", "kty": "EC"}),
+            ),
+            (
+                KeyAlgorithm::EdDsa,
+                serde_json::json!({"alg": "EdDSA", "crv": "Ed25519", "kty": "OKP"}),
+            ),
+        ] {
+            let keypair =
+                AccountKey::from_pkcs8(&generate_pkcs8_keypair(algorithm).unwrap()).unwrap();
+            let jwk = serde_json::to_value(keypair.jwk()).unwrap();
+            assert_eq!(jwk["alg"], expected["alg"]);
+            assert_eq!(jwk["crv"], expected["crv"]);
+            assert_eq!(jwk["kty"], expected["kty"]);
+            assert_eq!(jwk["y"].is_string(), algorithm != KeyAlgorithm::EdDsa);
+            let jws = jose(&keypair, None, Some("kid"), Some("nonce"), "url")
+                .await
+                .unwrap();
+            let protected: Value = serde_json::from_slice(
+                &BASE64_URL_SAFE_NO_PAD
+                    .decode(jws["protected"].as_str().unwrap())
+                    .unwrap(),
+            )
+            .unwrap();
+            assert_eq!(protected["alg"], expected["alg"]);
+        }
+    }
+}
@@ -1,13 +1,16 @@
 pub extern crate rcgen;
 #[cfg(feature = "reqwest")]
 pub extern crate reqwest;
+pub extern crate time;
 
-use crate::account::AccountMaterial;
 use crate::client::{HttpClient, Response};
-use crate::directory::Directory;
 use crate::errors::Result;
+use crate::key::AccountKey;
 use crate::order::LocatedOrder;
+use crate::renewal::RenewalInfo;
 use crate::resolver::CertResolver;
+use crate::revocation::{RevocationKey, RevocationReason};
+use futures::StreamExt;
 use rustls::sign::CertifiedKey;
 use std::fmt::Debug;
 use std::ops::Deref;
@@ -15,20 +18,36 @@ use std::sync::Arc;
 
 mod account;
 mod authorization;
+mod certificate;
 mod challenge;
 mod client;
 mod csr;
 mod directory;
 pub mod ecdsa;
-mod errors;
+pub mod errors;
 mod jose;
+pub mod key;
 pub mod letsencrypt;
 mod order;
+pub mod renewal;
 pub mod resolver;
+pub mod revocation;
 
 #[cfg(feature = "reqwest")]
 mod reqwest_client;
 
+#[cfg(test)]
+mod mock;
+
+pub use account::{Account, AccountMaterial, AccountStatus, Contacts, ExternalAccountBinding};
+pub use authorization::{AuthorizationStatus, PreAuthorization};
+pub use certificate::{CertificateChain, PreferredChain};
+pub use directory::{Directory, DirectoryMeta};
+pub use order::{AccountOrder, Identifier, OrderGrouping, OrderOptions, OrderStatus, PendingOrder};
+
+/// Maximum number of orders processed (or fetched) at the same time.
+pub(crate) const MAX_CONCURRENT_ORDERS: usize = 4;
+
 #[cfg(test)]
 pub(crate) static INIT: std::sync::Once = std::sync::Once::new();
 
@@ -76,6 +95,8 @@ impl<C: HttpClient<R> + Default, R: Response> Acme<R, C> {
     ) -> Self {
         Self::from_client_and_domain_keys(C::default(), domain_names)
     }
+    /// The domain names can also be ip addresses, but only some ACME servers
+    /// issue certificates for [ip addresses](https://datatracker.ietf.org/doc/html/rfc8738).
     pub fn from_domain_names(domain_names: impl Iterator<Item = impl Into<String>>) -> Self {
         Self::from_domain_keys(domain_names.into_iter().map(|it| (it, None)))
     }
@@ -94,13 +115,26 @@ impl<C: HttpClient<R> + Default, R: Response> Acme<R, C> {
     pub async fn directory(&self, directory_url: impl AsRef<str> + Debug) -> Result<Directory> {
         Directory::from(directory_url, &self.client).await
     }
-    /// Create a new account with the specified contact email.
+    /// Create a new account with the specified contacts
+    /// (email addresses or URIs, see [Contacts], possibly none).
+    /// Creating the account agrees to the
+    /// [terms of service](DirectoryMeta::terms_of_service) of the ACME server.
     pub async fn new_account(
         &self,
-        contact_email: impl AsRef<str>,
+        contacts: impl Into<Contacts>,
         directory: &Directory,
     ) -> Result<AccountMaterial> {
-        AccountMaterial::from(contact_email, directory, &self.client).await
+        AccountMaterial::from(contacts, None, directory, &self.client).await
+    }
+    /// Create a new account with the specified contacts,
+    /// bound to the specified external account.
+    pub async fn new_account_with_eab(
+        &self,
+        contacts: impl Into<Contacts>,
+        eab: &ExternalAccountBinding,
+        directory: &Directory,
+    ) -> Result<AccountMaterial> {
+        AccountMaterial::from(contacts, Some(eab), directory, &self.client).await
     }
     /// Request a new certificate and update the resolver.
     pub async fn request_certificates(
@@ -108,9 +142,259 @@ impl<C: HttpClient<R> + Default, R: Response> Acme<R, C> {
         account: &AccountMaterial,
         directory: &Directory,
     ) -> Result<String> {
-        LocatedOrder::new_order(self.domains.iter(), account, directory, &self.client)
+        self.request_certificates_with_options(&OrderOptions::default(), account, directory)
+            .await
+    }
+    /// Request a new certificate with the specified order options (e.g. profile, chain),
+    /// and update the resolver.
+    pub async fn request_certificates_with_options(
+        &mut self,
+        options: &OrderOptions,
+        account: &AccountMaterial,
+        directory: &Directory,
+    ) -> Result<String> {
+        LocatedOrder::new_order(
+            self.domains.iter(),
+            None,
+            options,
+            account,
+            directory,
+            &self.client,
+        )
+        .await?
+        .process(account, directory, &self.resolver, &self.client)
+        .await
+    }
+    /// Get the account object (contacts, status, ...).
+    pub async fn account(
+        &self,
+        account: &AccountMaterial,
+        directory: &Directory,
+    ) -> Result<Account> {
+        account.account(directory, &self.client).await
+    }
+    /// List the orders of the account.
+    /// This fails if one of the orders can't be fetched.
+    pub async fn orders(
+        &self,
+        account: &AccountMaterial,
+        directory: &Directory,
+    ) -> Result<Vec<AccountOrder>> {
+        account.orders(directory, &self.client).await
+    }
+    /// [Pre-authorize](https://datatracker.ietf.org/doc/html/rfc8555#section-7.4.1)
+    /// the specified domains, which need to be managed by the resolver,
+    /// so that later orders for those domains can be completed without a new validation
+    /// until the authorizations expire.
+    /// This fails with [crate::errors::ErrorKind::Challenge] if some validations are still pending
+    /// after a minute.
+    /// Not all ACME servers support pre-authorization
+    /// (see [Directory::supports_pre_authorization]).
+    pub async fn pre_authorize(
+        &self,
+        domain_names: impl Iterator<Item = impl Into<String>>,
+        account: &AccountMaterial,
+        directory: &Directory,
+    ) -> Result<Vec<PreAuthorization>> {
+        authorization::pre_authorize(
+            domain_names.map(|it| Identifier::from(it.into())).collect(),
+            account,
+            directory,
+            &self.resolver,
+            &self.client,
+        )
+        .await
+    }
+    /// [Deactivate](https://datatracker.ietf.org/doc/html/rfc8555#section-7.5.2)
+    /// the specified authorizations (e.g. [PreAuthorization::url] or
+    /// [AccountOrder::authorizations]) that are still pending or valid,
+    /// and return the final status of each authorization.
+    pub async fn deactivate_authorizations(
+        &self,
+        urls: impl Iterator<Item = impl Into<String>>,
+        account: &AccountMaterial,
+        directory: &Directory,
+    ) -> Result<Vec<AuthorizationStatus>> {
+        let urls: Vec<String> = urls.map(Into::into).collect();
+        authorization::deactivate_authorizations(&urls, |_| true, account, directory, &self.client)
+            .await
+    }
+    /// Stop managing the specified domains: their pending or valid authorizations among
+    /// the known ones (e.g. [PreAuthorization::url] or [AccountOrder::authorizations])
+    /// are deactivated, and then they are removed from the resolver.
+    /// If an authorization can't be deactivated, the domains are still managed.
+    pub async fn remove_domains(
+        &mut self,
+        domain_names: impl Iterator<Item = impl Into<String>>,
+        authorizations: impl Iterator<Item = impl Into<String>>,
+        account: &AccountMaterial,
+        directory: &Directory,
+    ) -> Result<Vec<AuthorizationStatus>> {
+        let identifiers: Vec<Identifier> =
+            domain_names.map(|it| Identifier::from(it.into())).collect();
+        let mut urls: Vec<String> = authorizations.map(Into::into).collect();
+        urls.sort();
+        urls.dedup();
+        let statuses = authorization::deactivate_authorizations(
+            &urls,
+            |it| identifiers.contains(&it.identifier),
+            account,
+            directory,
+            &self.client,
+        )
+        .await?;
+        self.domains
+            .retain(|it| !identifiers.contains(&Identifier::from(it.clone())));
+        self.resolver.remove(identifiers.iter());
+        Ok(statuses)
+    }
+    /// Create a new order without processing it.
+    /// The returned order can be saved, and then processed with [Acme::resume_order].
+    pub async fn new_order(
+        &self,
+        options: &OrderOptions,
+        account: &AccountMaterial,
+        directory: &Directory,
+    ) -> Result<PendingOrder> {
+        LocatedOrder::new_order(
+            self.domains.iter(),
+            None,
+            options,
+            account,
+            directory,
+            &self.client,
+        )
+        .await
+        .map(|it| it.into_pending())
+    }
+    /// Resume processing a saved order, from its current state,
+    /// and update the resolver once the certificate is issued.
+    pub async fn resume_order(
+        &mut self,
+        order: PendingOrder,
+        account: &AccountMaterial,
+        directory: &Directory,
+    ) -> Result<String> {
+        LocatedOrder::resume(order, account, directory, &self.client)
             .await?
             .process(account, directory, &self.resolver, &self.client)
             .await
     }
+    /// Request new certificates for the domains split into groups, one order (and certificate)
+    /// per group, and update the resolver so that each domain uses the certificate covering it.
+    /// The orders are processed concurrently (a few at a time), and the result of each order
+    /// is returned along with its domains, so that a failing domain doesn't prevent
+    /// the other certificates from being issued.
+    pub async fn request_grouped_certificates(
+        &mut self,
+        grouping: OrderGrouping,
+        options: &OrderOptions,
+        account: &AccountMaterial,
+        directory: &Directory,
+    ) -> Vec<(Vec<String>, Result<String>)> {
+        let (client, resolver) = (&self.client, self.resolver.as_ref());
+        let groups = grouping.groups(&self.domains);
+        futures::stream::iter(groups)
+            .map(|domain_names| async move {
+                let result = match LocatedOrder::new_order(
+                    domain_names.iter(),
+                    None,
+                    options,
+                    account,
+                    directory,
+                    client,
+                )
+                .await
+                {
+                    Ok(order) => order.process(account, directory, resolver, client).await,
+                    Err(err) => Err(err),
+                };
+                (domain_names.to_vec(), result)
+            })
+            .buffered(MAX_CONCURRENT_ORDERS)
+            .collect()
+            .await
+    }
+    /// Request a new certificate that replaces the specified certificate (PEM or DER),
+    /// and update the resolver.
+    /// The ACME server can exempt replacement orders from rate limits
+    /// when the renewal was requested through its renewal info.
+    pub async fn replace_certificates(
+        &mut self,
+        certificate: impl AsRef<[u8]>,
+        options: &OrderOptions,
+        account: &AccountMaterial,
+        directory: &Directory,
+    ) -> Result<String> {
+        let replaces = certificate::certificate_der(certificate.as_ref())
+            .and_then(|it| certificate::ari_identifier(&it))?;
+        LocatedOrder::new_order(
+            self.domains.iter(),
+            Some(&replaces),
+            options,
+            account,
+            directory,
+            &self.client,
+        )
+        .await?
+        .process(account, directory, &self.resolver, &self.client)
+        .await
+    }
+    /// List the certificate chains offered by the ACME server for the certificate
+    /// at the specified url (e.g. from [OrderStatus::Valid]), the default chain first,
+    /// so that one can be picked with [OrderOptions::preferred_chain].
+    pub async fn certificate_chains(
+        &self,
+        certificate_url: impl AsRef<str>,
+        account: &AccountMaterial,
+        directory: &Directory,
+    ) -> Result<Vec<CertificateChain>> {
+        LocatedOrder::certificate_chains(certificate_url.as_ref(), account, directory, &self.client)
+            .await
+    }
+    /// Get the suggested renewal window for the certificate (PEM or DER).
+    pub async fn renewal_info(
+        &self,
+        certificate: impl AsRef<[u8]>,
+        directory: &Directory,
+    ) -> Result<RenewalInfo> {
+        renewal::renewal_info(certificate.as_ref(), directory, &self.client).await
+    }
+    /// Revoke a certificate (PEM or DER), signing the request with the account key.
+    pub async fn revoke(
+        &self,
+        certificate: impl AsRef<[u8]>,
+        reason: RevocationReason,
+        account: &AccountMaterial,
+        directory: &Directory,
+    ) -> Result<()> {
+        revocation::revoke(
+            certificate.as_ref(),
+            reason,
+            RevocationKey::Account(account),
+            directory,
+            &self.client,
+        )
+        .await
+    }
+    /// Revoke a certificate (PEM or DER), signing the request with the certificate
+    /// private key (PEM encoded PKCS#8).
+    /// This doesn't require the account that requested the certificate.
+    pub async fn revoke_with_certificate_key(
+        &self,
+        certificate: impl AsRef<[u8]>,
+        reason: RevocationReason,
+        private_key_pem: impl AsRef<[u8]>,
+        directory: &Directory,
+    ) -> Result<()> {
+        let keypair = AccountKey::from_pem(private_key_pem.as_ref())?;
+        revocation::revoke(
+            certificate.as_ref(),
+            reason,
+            RevocationKey::Certificate(&keypair),
+            directory,
+            &self.client,
+        )
+        .await
+    }
 }
@@ -1,5 +1,5 @@
-use acme_tls_alpn_01::Acme;
 use acme_tls_alpn_01::letsencrypt::LetsEncrypt::{ProductionEnvironment, StagingEnvironment};
+use acme_tls_alpn_01::{Acme, Contacts, OrderOptions, PreferredChain};
 use clap::error::ErrorKind;
 use clap::{Arg, ArgAction, ArgGroup, Command};
 use rustls::crypto;
@@ -48,8 +48,31 @@ async fn main() -> std::io::Result<()> {
         .arg(
             Arg::new("email")
                 .long("email")
-                .help("Contact email (defaults to contact@{first_domain})")
+                .visible_alias("contact")
+                .help("Contact email or URI, can be repeated (defaults to no contact)")
                 .value_name("email")
+                .num_args(1)
+                .action(ArgAction::Append),
+        )
+        .arg(
+            Arg::new("profile")
+                .long("profile")
+                .help("Certificate profile (e.g. shortlived)")
+                .value_name("name")
+                .num_args(1),
+        )
+        .arg(
+            Arg::new("preferred_chain")
+                .long("preferred-chain")
+                .help("Prefer the chain with this root common name (e.g. \"ISRG Root X1\")")
+                .value_name("root")
+                .num_args(1),
+        )
+        .arg(
+            Arg::new("preferred_issuer")
+                .long("preferred-issuer")
+                .help("Prefer the chain with this intermediate common name (e.g. \"R11\")")
+                .value_name("issuer")
                 .num_args(1),
         )
         .arg(
@@ -77,6 +100,11 @@ async fn main() -> std::io::Result<()> {
             ArgGroup::new("environment")
                 .args(["prod", "staging", "directory"])
                 .multiple(false),
+        )
+        .group(
+            ArgGroup::new("chain")
+                .args(["preferred_chain", "preferred_issuer"])
+                .multiple(false),
         );
     let matches = match cmd.try_get_matches_from_mut(args()) {
         Ok(matches) => matches,
@@ -95,15 +123,7 @@ async fn main() -> std::io::Result<()> {
         .get_many::<String>("domains")
         .expect("domains argument is required")
         .collect::<Vec<_>>();
-    let email = matches
-        .get_one::<Cow<str>>("email")
-        .cloned()
-        .unwrap_or_else(|| {
-            Cow::Owned(format!(
-                "contact@{}",
-                domain_names.first().expect("domains argument is required")
-            ))
-        });
+    let contacts = Contacts::new(matches.get_many::<String>("email").unwrap_or_default());
     let directory_url = match matches.get_one::<Cow<str>>("directory") {
         Some(url) => url.clone(),
         None => {
@@ -176,9 +196,23 @@ async fn main() -> std::io::Result<()> {
         }
     });
     let directory = acme.directory(directory_url).await.unwrap();
-    let account = acme.new_account(email, &directory).await.unwrap();
+    let account = acme.new_account(contacts, &directory).await.unwrap();
+    let options = OrderOptions {
+        profile: matches.get_one::<String>("profile").cloned(),
+        preferred_chain: matches
+            .get_one::<String>("preferred_chain")
+            .cloned()
+            .map(PreferredChain::Root)
+            .or_else(|| {
+                matches
+                    .get_one::<String>("preferred_issuer")
+                    .cloned()
+                    .map(PreferredChain::Issuer)
+            }),
+        ..Default::default()
+    };
     let certificate = acme
-        .request_certificates(&account, &directory)
+        .request_certificates_with_options(&options, &account, &directory)
         .await
         .unwrap();
     if let Some(out) = matches
@@ -0,0 +1,197 @@
+use crate::client::{HttpClient, Response};
+use crate::directory::Directory;
+use crate::errors::{Error, ErrorKind, Result};
+use base64::Engine;
+use base64::prelude::BASE64_URL_SAFE_NO_PAD;
+use serde::de::DeserializeOwned;
+use serde_json::{Value, json};
+use std::borrow::Borrow;
+use std::collections::{HashMap, VecDeque};
+use std::sync::Mutex;
+
+pub(crate) const NEW_NONCE_URL: &str = "https://example.com/acme/new-nonce";
+
+/// A directory with the example urls of RFC 8555.
+pub(crate) fn directory() -> Directory {
+    serde_json::from_value::<Directory>(json!({
+        "newNonce": NEW_NONCE_URL,
+        "newAccount": "https://example.com/acme/new-account",
+        "newOrder": "https://example.com/acme/new-order",
+        "newAuthz": "https://example.com/acme/new-authz",
+        "revokeCert": "https://example.com/acme/revoke-cert",
+        "keyChange": "https://example.com/acme/key-change",
+        "renewalInfo": "https://example.com/acme/renewal-info"
+    }))
+    .unwrap()
+}
+
+/// Client that replies with the responses registered for each url, instead of
+/// reaching an ACME server, and records the signed requests.
+/// The responses for a url are returned in order, and the last one is repeated.
+/// Requests to a url without responses fail with [ErrorKind::ConnectionError].
+#[derive(Debug, Default)]
+pub(crate) struct MockClient {
+    responses: Mutex<HashMap<String, VecDeque<MockResponse>>>,
+    requests: Mutex<Vec<(String, Value)>>,
+}
+
+impl MockClient {
+    /// A client with a newNonce endpoint.
+    pub(crate) fn new() -> Self {
+        Self::default().on(NEW_NONCE_URL, MockResponse::new(200))
+    }
+    /// Add a response for the url.
+    pub(crate) fn on(self, url: impl Into<String>, response: MockResponse) -> Self {
+        self.responses
+            .lock()
+            .unwrap()
+            .entry(url.into())
+            .or_default()
+            .push_back(response);
+        self
+    }
+    /// The url and decoded payload of the signed requests, in order
+    /// (the payload of POST-as-GET requests is null).
+    pub(crate) fn requests(&self) -> Vec<(String, Value)> {
+        self.requests
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(url, body)| (url.clone(), decode_payload(body)))
+            .collect()
+    }
+    /// The JWS bodies of the signed requests to the url, to check their protected headers
+    /// and signatures.
+    pub(crate) fn bodies(&self, url: &str) -> Vec<Value> {
+        self.requests
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|(it, _)| it == url)
+            .map(|(_, body)| body.clone())
+            .collect()
+    }
+    /// The decoded payloads of the signed requests to the url.
+    pub(crate) fn payloads(&self, url: &str) -> Vec<Value> {
+        self.requests()
+            .into_iter()
+            .filter(|(it, _)| it == url)
+            .map(|(_, payload)| payload)
+            .collect()
+    }
+    fn respond(&self, url: &str) -> Result<MockResponse> {
+        let mut responses = self.responses.lock().unwrap();
+        let queue = responses
+            .get_mut(url)
+            .ok_or::<Error>(ErrorKind::ConnectionError.into())?;
+        match queue.len() {
+            0 => Err(ErrorKind::ConnectionError.into()),
+            1 => Ok(queue[0].clone()),
+            _ => Ok(queue.pop_front().unwrap()),
+        }
+    }
+}
+
+impl HttpClient<MockResponse> for MockClient {
+    async fn get_request(&self, url: impl AsRef<str>) -> Result<MockResponse> {
+        self.respond(url.as_ref())
+    }
+    async fn post_jose(
+        &self,
+        url: impl AsRef<str>,
+        body: impl Borrow<Value>,
+    ) -> Result<MockResponse> {
+        self.requests
+            .lock()
+            .unwrap()
+            .push((url.as_ref().to_string(), body.borrow().clone()));
+        self.respond(url.as_ref())
+    }
+}
+
+/// The decoded payload of a JWS, null if it is empty or not json.
+pub(crate) fn decode_payload(jws: &Value) -> Value {
+    jws["payload"]
+        .as_str()
+        .filter(|it| !it.is_empty())
+        .and_then(|it| BASE64_URL_SAFE_NO_PAD.decode(it).ok())
+        .and_then(|it| serde_json::from_slice(&it).ok())
+        .unwrap_or(Value::Null)
+}
+
+/// Response with a status, headers and a body.
+/// A `Replay-Nonce` header is always included.
+#[derive(Debug, Clone)]
+pub(crate) struct MockResponse {
+    status: u16,
+    headers: Vec<(String, String)>,
+    body: String,
+}
+
+impl MockResponse {
+    /// Empty response with the status.
+    pub(crate) fn new(status: u16) -> Self {
+        Self {
+            status,
+            headers: vec![("replay-nonce".to_string(), "nonce".to_string())],
+            body: String::new(),
+        }
+    }
+    /// Successful response with a json body.
+    pub(crate) fn json(body: Value) -> Self {
+        Self::new(200).with_body(body.to_string())
+    }
+    /// Error response with a problem document.
+    pub(crate) fn problem(status: u16, kind: &str, detail: &str) -> Self {
+        Self::new(status).with_body(
+            json!({
+                "type": format!("urn:ietf:params:acme:error:{kind}"),
+                "detail": detail
+            })
+            .to_string(),
+        )
+    }
+    pub(crate) fn with_body(mut self, body: impl Into<String>) -> Self {
+        self.body = body.into();
+        self
+    }
+    /// Add a header value. Headers that can be repeated (e.g. `Link`) can be added several times.
+    pub(crate) fn with_header(mut self, name: &str, value: impl Into<String>) -> Self {
+        self.headers.retain(|(it, _)| it != name || name == "link");
+        self.headers.push((name.to_string(), value.into()));
+        self
+    }
+}
+
+impl Response for MockResponse {
+    fn status_code(&self) -> u16 {
+        self.status
+    }
+    fn is_success(&self) -> bool {
+        (200..300).contains(&self.status)
+    }
+    fn header_value(&self, header_name: impl AsRef<str>) -> Option<String> {
+        self.header_values(header_name).into_iter().next()
+    }
+    fn header_values(&self, header_name: impl AsRef<str>) -> Vec<String> {
+        self.headers
+            .iter()
+            .filter(|(name, _)| name.eq_ignore_ascii_case(header_name.as_ref()))
+            .map(|(_, value)| value.clone())
+            .collect()
+    }
+    async fn body_as_json<T: DeserializeOwned>(self) -> Result<T> {
+        serde_json::from_str(&self.body).map_err(|_| {
+            ErrorKind::DeserializationError {
+                type_name: std::any::type_name::<T>().to_string(),
+            }
+            .into()
+        })
+    }
+    async fn body_as_text(self) -> Result<String> {
+        Ok(self.body)
+    }
+    async fn body_as_bytes(self) -> Result<impl Borrow<[u8]>> {
+        Ok(self.body.into_bytes())
+    }
+}
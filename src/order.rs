@@ -1,30 +1,171 @@
+use crate::MAX_CONCURRENT_ORDERS;
 use crate::account::AccountMaterial;
-use crate::authorization::{Authorization, AuthorizationStatus};
-use crate::challenge::{Challenge, ChallengeStatus, ChallengeType};
+use crate::authorization::{AuthorizationStatus, accept_challenges, deactivate_authorizations};
+use crate::certificate::{CertificateChain, PreferredChain, chain_issuers};
 use crate::client::{HttpClient, Response};
 use crate::csr::Csr;
 use crate::directory::Directory;
 use crate::errors::{Error, ErrorKind, Result};
 use crate::jose::jose;
-use crate::resolver::{CertResolver, DomainResolver};
+use crate::resolver::{CertResolver, certified_key_from_pem};
 use base64::Engine;
-use futures::future::{select, Either};
-use futures::stream::FuturesUnordered;
-use futures::StreamExt;
+use futures::{StreamExt, TryStreamExt, stream};
 use futures_timer::Delay;
 use serde::{Deserialize, Serialize};
 use serde_json::json;
-use std::fmt::{Debug, Display, Formatter};
-use std::sync::Arc;
-use std::time::Duration;
+use std::collections::HashSet;
+use std::fmt::{Debug, Display, Formatter, Write};
+use std::net::IpAddr;
+use std::time::{Duration, Instant};
+use time::OffsetDateTime;
+use time::format_description::well_known::Rfc3339;
 #[cfg(feature = "tracing")]
 use tracing::debug;
 
+/// Maximum time spent processing an order, including the challenge validations.
+const ORDER_DEADLINE: Duration = Duration::from_secs(600);
+/// Delay between polls when the ACME server doesn't specify a `Retry-After`.
+pub(crate) const DEFAULT_POLL_INTERVAL: Duration = Duration::from_secs(5);
+/// Lower bound on the delay between polls.
+const MIN_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
 /// Order with its url that we can use to poll its status.
 #[derive(Debug)]
 pub(crate) struct LocatedOrder {
-    url: String,
+    pending: PendingOrder,
     pub(crate) order: Order,
+    /// The delay requested by the ACME server before polling the order again.
+    retry_after: Option<Duration>,
+}
+
+/// The order url, along with the CSR (and its private key) that will be used to finalize it.
+/// It can be saved so that the order can be resumed, after a restart for instance,
+/// instead of creating a new order.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct PendingOrder {
+    url: String,
+    csr: Csr,
+    #[serde(rename = "preferredChain", skip_serializing_if = "Option::is_none")]
+    preferred_chain: Option<PreferredChain>,
+    #[serde(
+        rename = "deactivateAuthorizationsOnFailure",
+        default,
+        skip_serializing_if = "std::ops::Not::not"
+    )]
+    deactivate_authorizations_on_failure: bool,
+}
+
+impl PendingOrder {
+    /// The order url.
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+    /// Serialize to json
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).expect("failed to serialize pending order")
+    }
+    /// Deserialize from json
+    pub fn from_json(json: impl AsRef<str>) -> Result<Self> {
+        serde_json::from_str(json.as_ref()).map_err(|_| {
+            ErrorKind::DeserializationError {
+                type_name: "PendingOrder".to_string(),
+            }
+            .into()
+        })
+    }
+}
+
+/// How the domains are split into orders, each order resulting in one certificate.
+/// The ACME servers limit the number of domains per certificate (100 for Let's Encrypt).
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum OrderGrouping {
+    /// One certificate for all the domains.
+    #[default]
+    All,
+    /// One certificate per domain.
+    PerDomain,
+    /// One certificate for each chunk of (at most) N domains.
+    Chunks(usize),
+}
+
+impl OrderGrouping {
+    /// Split the domains into groups, one for each order.
+    pub(crate) fn groups<'a>(&self, domain_names: &'a [String]) -> Vec<&'a [String]> {
+        match self {
+            OrderGrouping::All => vec![domain_names],
+            OrderGrouping::PerDomain => domain_names.chunks(1).collect(),
+            OrderGrouping::Chunks(size) => domain_names.chunks((*size).max(1)).collect(),
+        }
+    }
+}
+
+/// Options for new orders.
+#[derive(Debug, Default, Clone)]
+pub struct OrderOptions {
+    /// The certificate profile, which should be one of the profiles
+    /// advertised in the [directory metadata](crate::DirectoryMeta::profiles).
+    pub profile: Option<String>,
+    /// The preferred certificate chain, when the ACME server offers alternate chains.
+    pub preferred_chain: Option<PreferredChain>,
+    /// The requested start of the certificate validity period, truncated to whole seconds.
+    /// Not all ACME servers support it.
+    pub not_before: Option<OffsetDateTime>,
+    /// The requested end of the certificate validity period, truncated to whole seconds.
+    /// Not all ACME servers support it.
+    pub not_after: Option<OffsetDateTime>,
+    /// Deactivate the pending authorizations of the order when it fails,
+    /// so that they don't count against the pending authorizations limit of the account.
+    pub deactivate_authorizations_on_failure: bool,
+}
+
+impl OrderOptions {
+    /// Check that the options are supported by the ACME server.
+    fn validate(&self, directory: &Directory) -> Result<()> {
+        if let Some(profile) = &self.profile
+            && !directory.meta().profiles.contains_key(profile)
+        {
+            return Err(ErrorKind::UnsupportedProfile {
+                profile: profile.clone(),
+            }
+            .into());
+        }
+        if let (Some(not_before), Some(not_after)) = (self.not_before, self.not_after)
+            && not_before >= not_after
+        {
+            return Err(ErrorKind::OrderValidity.with_msg("notBefore is not before notAfter"));
+        }
+        Ok(())
+    }
+    /// The requested validity period, truncated to whole seconds
+    /// because ACME servers don't keep the fractional part.
+    fn validity(&self) -> [(&'static str, Option<OffsetDateTime>); 2] {
+        [
+            ("notBefore", self.not_before.map(whole_seconds)),
+            ("notAfter", self.not_after.map(whole_seconds)),
+        ]
+    }
+    /// Check that the ACME server didn't ignore or change the requested validity period.
+    fn check_validity(&self, order: &Order) -> Result<()> {
+        for ((name, requested), actual) in self
+            .validity()
+            .into_iter()
+            .zip([order.not_before, order.not_after])
+        {
+            if let Some(requested) = requested
+                && Some(requested) != actual.map(whole_seconds)
+            {
+                return Err(ErrorKind::OrderValidity.with_msg(match actual {
+                    Some(actual) => format!("requested {name} {requested} but got {actual}"),
+                    None => format!("requested {name} {requested} was ignored"),
+                }));
+            }
+        }
+        Ok(())
+    }
+}
+
+fn whole_seconds(date: OffsetDateTime) -> OffsetDateTime {
+    date.replace_nanosecond(0).unwrap_or(date)
 }
 
 /// [RFC 8555 Directory](https://datatracker.ietf.org/doc/html/rfc8555#section-7.1.1)
@@ -35,12 +176,131 @@ pub(crate) struct Order {
     pub(crate) finalize: String,
     #[serde(flatten)]
     pub(crate) status: OrderStatus,
+    #[serde(default, with = "time::serde::rfc3339::option")]
+    pub(crate) expires: Option<OffsetDateTime>,
+    #[serde(rename = "notBefore", default, with = "time::serde::rfc3339::option")]
+    pub(crate) not_before: Option<OffsetDateTime>,
+    #[serde(rename = "notAfter", default, with = "time::serde::rfc3339::option")]
+    pub(crate) not_after: Option<OffsetDateTime>,
+}
+
+impl Order {
+    /// Get the order, along with the delay requested by the ACME server before polling it again.
+    #[cfg_attr(feature = "tracing", tracing::instrument(
+        name = "get_order",
+        skip(account,directory,client),
+        level = tracing::Level::TRACE,
+        err(level = tracing::Level::WARN)
+    ))]
+    pub(crate) async fn fetch<C: HttpClient<R>, R: Response>(
+        url: &str,
+        account: &AccountMaterial,
+        directory: &Directory,
+        client: &C,
+    ) -> Result<(Order, Option<Duration>)> {
+        let response = directory
+            .signed_request(
+                url,
+                async |nonce| {
+                    jose(&account.keypair, None, Some(&account.url), Some(nonce), url).await
+                },
+                client,
+            )
+            .await
+            .map_err(|err| ErrorKind::GetOrder.wrap(err))?;
+        let retry_after = response.retry_after();
+        let order = response
+            .body_as_json::<Order>()
+            .await
+            .map_err(|err| ErrorKind::GetOrder.wrap(err))?;
+        Ok((order, retry_after))
+    }
+}
+
+/// An order of the account, as listed by [AccountMaterial::orders].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AccountOrder {
+    pub url: String,
+    pub status: OrderStatus,
+    pub identifiers: Vec<Identifier>,
+    pub authorizations: Vec<String>,
+    pub expires: Option<OffsetDateTime>,
+}
+
+/// [RFC 8555 Orders List](https://datatracker.ietf.org/doc/html/rfc8555#section-7.1.2.1)
+#[derive(Deserialize, Debug)]
+struct OrdersList {
+    orders: Vec<String>,
+}
+
+/// Get all the orders of the orders list, following the `next` links for the following pages.
+/// The listing fails if one of the orders can't be fetched, rather than returning
+/// an incomplete list.
+#[cfg_attr(feature = "tracing", tracing::instrument(
+    name = "list_orders",
+    skip(account,directory,client),
+    level = tracing::Level::DEBUG,
+    err(level = tracing::Level::WARN)
+))]
+pub(crate) async fn list_orders<C: HttpClient<R>, R: Response>(
+    orders_url: &str,
+    account: &AccountMaterial,
+    directory: &Directory,
+    client: &C,
+) -> Result<Vec<AccountOrder>> {
+    let mut orders = Vec::new();
+    // Stop if a `next` link points back to a page that was already listed.
+    let mut visited = HashSet::new();
+    let mut next = Some(orders_url.to_string());
+    while let Some(url) = next.take().filter(|it| visited.insert(it.clone())) {
+        let response = directory
+            .signed_request(
+                &url,
+                async |nonce| {
+                    jose(
+                        &account.keypair,
+                        None,
+                        Some(&account.url),
+                        Some(nonce),
+                        &url,
+                    )
+                    .await
+                },
+                client,
+            )
+            .await
+            .map_err(|err| ErrorKind::ListOrders.wrap(err))?;
+        next = response.links("next").into_iter().next();
+        let list = response
+            .body_as_json::<OrdersList>()
+            .await
+            .map_err(|err| ErrorKind::ListOrders.wrap(err))?;
+        let page: Vec<_> = stream::iter(list.orders.iter())
+            .map(|url| Order::fetch(url, account, directory, client))
+            .buffered(MAX_CONCURRENT_ORDERS)
+            .try_collect()
+            .await
+            .map_err(|err| ErrorKind::ListOrders.wrap(err))?;
+        orders.extend(
+            list.orders
+                .into_iter()
+                .zip(page)
+                .map(|(url, (order, _))| AccountOrder {
+                    url,
+                    status: order.status,
+                    identifiers: order.identifiers,
+                    authorizations: order.authorizations,
+                    expires: order.expires,
+                }),
+        );
+    }
+    Ok(orders)
 }
 
 /// [RFC 8555 Order States](https://datatracker.ietf.org/doc/html/rfc8555#page-32)
-#[derive(Deserialize, Debug, PartialEq, Eq)]
+#[derive(Deserialize, Debug, Clone, PartialEq, Eq)]
 #[serde(tag = "status")]
-pub(crate) enum OrderStatus {
+pub enum OrderStatus {
     #[serde(rename = "pending")]
     Pending,
     #[serde(rename = "ready")]
@@ -71,6 +331,75 @@ impl Display for OrderStatus {
 pub enum Identifier {
     #[serde(rename = "dns")]
     Dns(String),
+    /// [RFC 8738 IP Identifier](https://datatracker.ietf.org/doc/html/rfc8738#section-3)
+    #[serde(rename = "ip")]
+    Ip(IpAddr),
+}
+
+impl Identifier {
+    /// The server name sent by the ACME server during the tls-alpn-01 validation,
+    /// which is the [reverse dns name](https://datatracker.ietf.org/doc/html/rfc8738#section-6)
+    /// for ip addresses.
+    pub(crate) fn server_name(&self) -> String {
+        match self {
+            Identifier::Dns(name) => name.clone(),
+            Identifier::Ip(IpAddr::V4(ip)) => {
+                let mut name = String::with_capacity(29);
+                for octet in ip.octets().iter().rev() {
+                    let _ = write!(name, "{octet}.");
+                }
+                name.push_str("in-addr.arpa");
+                name
+            }
+            Identifier::Ip(IpAddr::V6(ip)) => {
+                let mut name = String::with_capacity(73);
+                for octet in ip.octets().iter().rev() {
+                    let _ = write!(name, "{:x}.{:x}.", octet & 0x0f, octet >> 4);
+                }
+                name.push_str("ip6.arpa");
+                name
+            }
+        }
+    }
+}
+
+/// An ip identifier if the name is an ip address, a dns identifier otherwise.
+impl From<String> for Identifier {
+    fn from(value: String) -> Self {
+        match value.parse::<IpAddr>() {
+            Ok(ip) => Identifier::Ip(ip),
+            Err(_) => Identifier::Dns(value),
+        }
+    }
+}
+
+impl Display for Identifier {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Identifier::Dns(name) => f.write_str(name),
+            Identifier::Ip(ip) => Display::fmt(ip, f),
+        }
+    }
+}
+
+/// The delay before polling the order again: the `Retry-After` requested by the ACME server
+/// (or the default interval), but not past the deadline.
+/// There is no delay if the deadline is too close for another poll.
+pub(crate) fn poll_delay(
+    retry_after: Option<Duration>,
+    now: Instant,
+    deadline: Instant,
+) -> Option<Duration> {
+    let remaining = deadline.saturating_duration_since(now);
+    if remaining < MIN_POLL_INTERVAL {
+        return None;
+    }
+    Some(
+        retry_after
+            .unwrap_or(DEFAULT_POLL_INTERVAL)
+            .max(MIN_POLL_INTERVAL)
+            .min(remaining),
+    )
 }
 
 impl LocatedOrder {
@@ -84,54 +413,110 @@ impl LocatedOrder {
     ))]
     pub(crate) async fn new_order<C: HttpClient<R>, R: Response>(
         domain_names: impl Iterator<Item = impl Into<String>> + Debug,
+        replaces: Option<&str>,
+        options: &OrderOptions,
         account: &AccountMaterial,
         directory: &Directory,
         client: &C,
     ) -> Result<LocatedOrder> {
+        options.validate(directory)?;
         let domain_names: Vec<String> = domain_names.map(|it| it.into()).collect();
-        let nonce = directory.new_nonce(client).await?;
-        let identifiers: Vec<Identifier> = domain_names
-            .iter()
-            .map(|it| Identifier::Dns(it.clone()))
-            .collect();
-        let payload = json!({
+        // The CSR is created upfront so that it can be saved along with the order url.
+        let csr: Csr = domain_names.clone().try_into()?;
+        let identifiers: Vec<Identifier> = domain_names.into_iter().map(Identifier::from).collect();
+        let mut payload = json!({
             "identifiers": identifiers
         });
-        let body = jose(
-            &account.keypair,
-            Some(payload),
-            Some(&account.url),
-            Some(&nonce),
-            &directory.new_order,
-        );
-        let response = client
-            .post_jose(&directory.new_order, &body)
+        // [RFC 9773 Replacing Certificates](https://datatracker.ietf.org/doc/html/rfc9773#section-5)
+        if let Some(replaces) = replaces {
+            payload["replaces"] = json!(replaces);
+        }
+        // [ACME Profiles](https://datatracker.ietf.org/doc/html/draft-aaron-acme-profiles)
+        if let Some(profile) = &options.profile {
+            payload["profile"] = json!(profile);
+        }
+        for (name, date) in options.validity() {
+            if let Some(date) = date {
+                payload[name] = json!(date.format(&Rfc3339).map_err(|_| {
+                    ErrorKind::OrderValidity.with_msg(format!("invalid {name} {date}"))
+                })?);
+            }
+        }
+        let response = directory
+            .signed_request(
+                &directory.new_order,
+                async |nonce| {
+                    jose(
+                        &account.keypair,
+                        Some(payload.clone()),
+                        Some(&account.url),
+                        Some(nonce),
+                        &directory.new_order,
+                    )
+                    .await
+                },
+                client,
+            )
             .await
             .map_err(|err| ErrorKind::NewOrder.wrap(err))?;
-        if response.is_success() {
-            let url = response
-                .header_value("location")
-                .ok_or::<Error>(ErrorKind::NewOrder.into())?;
-            let order = response
-                .body_as_json::<Order>()
-                .await
-                .map_err(|err| ErrorKind::NewOrder.wrap(err))?;
-            Ok(LocatedOrder { url, order })
-        } else {
-            #[cfg(feature = "tracing")]
-            if let Ok(text) = response.body_as_text().await {
-                debug!(body = ?text);
-            }
-            #[cfg(not(feature = "tracing"))]
-            let _ = response.body_as_text();
-            Err(ErrorKind::NewOrder.into())
+        let url = response
+            .header_value("location")
+            .ok_or::<Error>(ErrorKind::NewOrder.into())?;
+        let retry_after = response.retry_after();
+        let order = response
+            .body_as_json::<Order>()
+            .await
+            .map_err(|err| ErrorKind::NewOrder.wrap(err))?;
+        if let Err(err) = options.check_validity(&order) {
+            // The order can't be used, deactivate its authorizations so that they don't
+            // count against the pending authorizations limit. The order itself can't be
+            // canceled, and is left behind until it expires.
+            let _ = deactivate_authorizations(
+                &order.authorizations,
+                |it| it.status == AuthorizationStatus::Pending,
+                account,
+                directory,
+                client,
+            )
+            .await;
+            return Err(err);
         }
+        Ok(LocatedOrder {
+            pending: PendingOrder {
+                url,
+                csr,
+                preferred_chain: options.preferred_chain.clone(),
+                deactivate_authorizations_on_failure: options.deactivate_authorizations_on_failure,
+            },
+            order,
+            retry_after,
+        })
+    }
+    /// Fetch the saved order to resume processing it from its current state.
+    pub(crate) async fn resume<C: HttpClient<R>, R: Response>(
+        pending: PendingOrder,
+        account: &AccountMaterial,
+        directory: &Directory,
+        client: &C,
+    ) -> Result<Self> {
+        Self::try_get(pending, account, directory, client).await
+    }
+    /// The order that can be saved to resume processing later.
+    pub(crate) fn into_pending(self) -> PendingOrder {
+        self.pending
     }
     /// Process the order: get the authorization challenges,
     /// setup the resolver to respond to those challenges,
     /// notify the ACME server to validate them,
     /// wait for the ACME server validation to be done,
-    /// finalize the order and download the certificate.
+    /// finalize the order, download the certificate and install it in the resolver.
+    ///
+    /// The order is fetched again after each step, and polled while it is pending
+    /// or processing, until the [ORDER_DEADLINE] is reached.
+    /// Resumed orders start from their current state.
+    ///
+    /// If the order fails, its pending authorizations are deactivated
+    /// when the order options asked for it.
     #[cfg_attr(feature = "tracing", tracing::instrument(
         name = "process_order",
         skip_all,
@@ -145,247 +530,140 @@ impl LocatedOrder {
         resolver: &CertResolver,
         client: &C,
     ) -> Result<String> {
-        // Once all the order has been finalized, the order might stay
-        // in the processing state for a little while.
-        // If that is the case, we wait for 10s, then retrieve the
-        // order status again. If it is still processing, then we
-        // wait for another 2:30s and retrieve the order status one
-        // last time. If it is still processing then we give up.
-        let mut delays = vec![150u64, 10u64];
-        let mut maybe_csr = None;
-        loop {
-            match self
-                .retry(account, directory, resolver, client, maybe_csr.take())
-                .await
-            {
-                Ok(it) => return Ok(it),
-                Err(Error {
-                    kind: ErrorKind::OrderProcessing { csr },
-                    ..
-                }) =>
-                {
-                    #[allow(unused)]
-                    if let Some(delay) = delays.pop() {
-                        #[cfg(feature = "tracing")]
-                        debug!("waiting {delay}s before checking order status again");
-                        let _ = maybe_csr.insert(csr);
-                    } else {
-                        return Err(ErrorKind::NewOrder.into());
-                    }
-                }
-                Err(err) => return Err(err),
-            }
-        }
-    }
-    /// Poll for the order status.
-    #[cfg_attr(feature = "tracing", tracing::instrument(
-        name = "get_order",
-        skip_all,
-        level = tracing::Level::TRACE,
-        err(level = tracing::Level::WARN)
-    ))]
-    async fn try_get<C: HttpClient<R>, R: Response>(
-        url: String,
-        account: &AccountMaterial,
-        directory: &Directory,
-        client: &C,
-    ) -> Result<Self> {
-        let nonce = directory.new_nonce(client).await?;
-        let body = jose(
-            &account.keypair,
-            None,
-            Some(&account.url),
-            Some(&nonce),
-            &url,
-        );
-        let response = client
-            .post_jose(&url, &body)
-            .await
-            .map_err(|err| ErrorKind::GetOrder.wrap(err))?;
-        if response.is_success() {
-            let order = response
-                .body_as_json::<Order>()
-                .await
-                .map_err(|err| ErrorKind::GetOrder.wrap(err))?;
-            Ok(LocatedOrder { url, order })
-        } else {
-            #[cfg(feature = "tracing")]
-            if let Ok(text) = response.body_as_text().await {
-                debug!(body = ?text);
-            }
-            #[cfg(not(feature = "tracing"))]
-            let _ = response.body_as_text();
-            Err(ErrorKind::GetOrder.into())
+        let authorizations = match self.pending.deactivate_authorizations_on_failure {
+            true => self.order.authorizations.clone(),
+            false => Vec::new(),
+        };
+        let result = self
+            .process_steps(
+                Instant::now() + ORDER_DEADLINE,
+                account,
+                directory,
+                resolver,
+                client,
+            )
+            .await;
+        if result.is_err() && !authorizations.is_empty() {
+            // The order failure is what should be reported, not a cleanup failure.
+            let _ = deactivate_authorizations(
+                &authorizations,
+                |it| it.status == AuthorizationStatus::Pending,
+                account,
+                directory,
+                client,
+            )
+            .await;
         }
+        result
     }
-    /// Take appropriate steps based on the order status:
-    /// - if the status is pending:
-    ///   setup the resolver to respond to the challenges, notify the acme server, wait for the validations and then
-    ///   finalize the order and download the certificate
-    /// - if the status is ready:
-    ///   finalize the order and download the certificate
-    /// - if the status is valid:
-    ///   download the certificate
-    async fn retry<C: HttpClient<R>, R: Response>(
-        &self,
+    async fn process_steps<C: HttpClient<R>, R: Response>(
+        self,
+        deadline: Instant,
         account: &AccountMaterial,
         directory: &Directory,
         resolver: &CertResolver,
         client: &C,
-        csr: Option<Csr>,
     ) -> Result<String> {
-        match &self.order.status {
-            // Unrecoverable error
-            OrderStatus::Invalid => Err(ErrorKind::InvalidOrder {
-                domains: self
-                    .order
-                    .identifiers
-                    .iter()
-                    .map(|it| match it {
-                        Identifier::Dns(name) => name.clone(),
-                    })
-                    .collect(),
-            }
-            .into()),
-            // Ready to finalize and download the certificate
-            OrderStatus::Ready => self.finalize(account, directory, client).await,
-            // Ready to download the certificate
-            OrderStatus::Valid { certificate: url } => {
-                if let Some(csr) = csr {
-                    Self::download_certificate(url, &csr, account, directory, client).await
-                } else {
-                    Err(ErrorKind::NewOrder.into())
-                }
-            }
-            // Still processing, will be retried unless we already retried too many times.
-            OrderStatus::Processing => {
-                if let Some(csr) = csr {
-                    Err(ErrorKind::OrderProcessing { csr }.into())
-                } else {
-                    Err(ErrorKind::NewOrder.into())
+        let mut located = self;
+        let mut challenges_accepted = false;
+        let mut finalized = false;
+        loop {
+            match &located.order.status {
+                // Unrecoverable error
+                OrderStatus::Invalid => {
+                    return Err(ErrorKind::InvalidOrder {
+                        domains: located.domain_names(),
+                    }
+                    .into());
                 }
-            }
-            // Waiting the for the authorization challenges to be validated.
-            OrderStatus::Pending => {
-                // Get the challenges for all the authorizations.
-                let futures: Vec<_> = self
-                    .order
-                    .authorizations
-                    .iter()
-                    .map(|url| Authorization::authorize(url, account, directory, client))
-                    .collect();
-                let authorizations = futures::future::try_join_all(futures).await?;
-                // We can stop early if one of the authorizations failed.
-                if authorizations.iter().any(|it| {
-                    !matches!(
-                        it.status,
-                        AuthorizationStatus::Valid | AuthorizationStatus::Pending
+                // Setup the resolver to respond to the challenges, notify the acme server
+                // and wait for the validations, then fetch the order again.
+                OrderStatus::Pending if !challenges_accepted => {
+                    accept_challenges(
+                        &located.order.authorizations,
+                        account,
+                        directory,
+                        resolver,
+                        client,
                     )
-                }) {
-                    return Err(ErrorKind::InvalidAuthorization.into());
+                    .await?;
+                    challenges_accepted = true;
+                    located = Self::try_get(located.pending, account, directory, client).await?;
+                    continue;
                 }
-                // Gather all the pending authorizations, and for each of them, select the tls-alpn-01 challenge
-                // and setup the resolver to respond to the validation request.
-                let mut pending_challenges = FuturesUnordered::<_>::new();
-                let guard = resolver.map.pin();
-                for authorization in authorizations {
-                    let Identifier::Dns(ref domain_name) = authorization.identifier;
-                    if matches!(authorization.status, AuthorizationStatus::Pending) {
-                        for ref challenge in authorization.challenges {
-                            if matches!(challenge.kind, ChallengeType::TlsAlpn01) {
-                                let resolver = guard.get(domain_name).unwrap();
-                                let (sender, receiver) = flume::bounded(1);
-                                let resolver = DomainResolver {
-                                    key: Arc::new(resolver.key.as_ref().clone()),
-                                    challenge_key: Some(Arc::new(Challenge::certificate(
-                                        domain_name,
-                                        &challenge.authorization_key(account),
-                                    )?)),
-                                    notifier: Some(sender),
-                                };
-                                guard.insert(domain_name.clone(), resolver);
-                                match challenge.accept(account, directory, client).await?.status {
-                                    ChallengeStatus::Processing | ChallengeStatus::Pending => {
-                                        pending_challenges.push(receiver.into_recv_async())
-                                    }
-                                    ChallengeStatus::Valid => {}
-                                    ChallengeStatus::Invalid => {
-                                        return Err(
-                                            ErrorKind::Challenge.with_msg("challenge is invalid")
-                                        );
-                                    }
-                                }
-                            }
-                        }
-                    }
-                }
-                // Wait for the ACME server to call our server for all the pending challenges.
-                // Timeout after 2 mins.
-                let mut delay = Delay::new(Duration::from_secs(120));
-                loop {
-                    let next = pending_challenges.next();
-                    match select(delay, next).await {
-                        Either::Left(_) => {
-                            return Err(ErrorKind::Challenge.into());
-                        }
-                        Either::Right((result, unresolved_delay)) => {
-                            match result {
-                                None => break,
-                                Some(Err(_)) => return Err(ErrorKind::Challenge.into()),
-                                _ => {}
-                            }
-                            delay = unresolved_delay;
-                        }
-                    }
+                // Ready to finalize
+                OrderStatus::Ready if !finalized => {
+                    located = located.finalize(account, directory, client).await?;
+                    finalized = true;
+                    continue;
                 }
-
-                // The order status might stay pending for a little while.
-                // If that's the case, we wait for 10s and check again.
-                // If the status is still pending, we wait for 2:30s and check
-                // one last time. If the status is still pending then we give up.
-                let mut delays = vec![150u64, 10u64];
-                loop {
-                    match Self::try_get(self.url.clone(), account, directory, client)
-                        .await?
-                        .order
-                        .status
-                    {
-                        // Unrecoverable error
-                        OrderStatus::Invalid => {
-                            return Err(ErrorKind::InvalidOrder {
-                                domains: self
-                                    .order
-                                    .identifiers
-                                    .iter()
-                                    .map(|it| match it {
-                                        Identifier::Dns(name) => name.clone(),
-                                    })
-                                    .collect(),
-                            }
-                            .into());
-                        }
-                        // Ready to finalize and download the certificate
-                        OrderStatus::Ready => {
-                            return self.finalize(account, directory, client).await;
-                        }
-                        // Still pending
-                        OrderStatus::Pending => {
-                            if let Some(delay) = delays.pop() {
-                                #[cfg(feature = "tracing")]
-                                debug!("waiting {delay}s before checking order status again");
-                                Delay::new(Duration::from_secs(delay)).await;
-                            } else {
-                                return Err(ErrorKind::NewOrder.into());
-                            }
-                        }
-                        _ => return Err(ErrorKind::NewOrder.into()),
-                    }
+                // Ready to download the certificate
+                OrderStatus::Valid { certificate } => {
+                    #[cfg(feature = "tracing")]
+                    debug!(download_url = certificate);
+                    let pem = Self::download_certificate(
+                        certificate,
+                        &located.pending.csr,
+                        located.pending.preferred_chain.as_ref(),
+                        account,
+                        directory,
+                        client,
+                    )
+                    .await?;
+                    let key = certified_key_from_pem(&pem)?;
+                    resolver.install(located.order.identifiers.iter(), key);
+                    return Ok(pem);
                 }
+                // Still pending or processing, poll again later.
+                _ => {}
             }
+            let Some(delay) = poll_delay(located.retry_after, Instant::now(), deadline) else {
+                return Err(if located.order.status == OrderStatus::Processing {
+                    ErrorKind::OrderProcessing {
+                        order: Box::new(located.pending),
+                    }
+                    .into()
+                } else {
+                    ErrorKind::OrderTimeout {
+                        status: located.order.status.to_string(),
+                    }
+                    .into()
+                });
+            };
+            #[cfg(feature = "tracing")]
+            debug!(
+                "order is {}, waiting {}s before checking order status again",
+                located.order.status,
+                delay.as_secs()
+            );
+            Delay::new(delay).await;
+            located = Self::try_get(located.pending, account, directory, client).await?;
         }
     }
+    /// Poll for the order status.
+    async fn try_get<C: HttpClient<R>, R: Response>(
+        pending: PendingOrder,
+        account: &AccountMaterial,
+        directory: &Directory,
+        client: &C,
+    ) -> Result<Self> {
+        let (order, retry_after) = Order::fetch(&pending.url, account, directory, client).await?;
+        Ok(LocatedOrder {
+            pending,
+            order,
+            retry_after,
+        })
+    }
+    /// The domain names (or ip addresses) of the order identifiers.
+    fn domain_names(&self) -> Vec<String> {
+        self.order
+            .identifiers
+            .iter()
+            .map(|it| it.to_string())
+            .collect()
+    }
     /// [RFC 8555 Finalizing the Order](https://datatracker.ietf.org/doc/html/rfc8555#section-page-46)
-    /// and if successful download the certificate.
+    /// with the CSR created with the order, and return the updated order.
     #[cfg_attr(feature = "tracing", tracing::instrument(
         name = "finalize_order",
         skip_all,
@@ -393,63 +671,49 @@ impl LocatedOrder {
         err(level = tracing::Level::WARN)
     ))]
     async fn finalize<C: HttpClient<R>, R: Response>(
-        &self,
+        self,
         account: &AccountMaterial,
         directory: &Directory,
         client: &C,
-    ) -> Result<String> {
+    ) -> Result<Self> {
         let url = &self.order.finalize;
-        let nonce = directory.new_nonce(client).await?;
-        let domain_names: Vec<String> = self
-            .order
-            .identifiers
-            .iter()
-            .filter_map(|identifier| match identifier {
-                Identifier::Dns(domain_name) => Some(domain_name.clone()),
-                #[allow(unreachable_patterns)]
-                _ => None,
-            })
-            .collect();
-        let csr: Csr = domain_names.try_into()?;
         let payload = json!({
-           "csr": base64::prelude::BASE64_URL_SAFE_NO_PAD.encode(&csr.der)
+           "csr": base64::prelude::BASE64_URL_SAFE_NO_PAD.encode(&self.pending.csr.der)
         });
-        let body = jose(
-            &account.keypair,
-            Some(payload),
-            Some(&account.url),
-            Some(&nonce),
-            url,
-        );
-        let response = client
-            .post_jose(&url, &body)
+        let response = directory
+            .signed_request(
+                url,
+                async |nonce| {
+                    jose(
+                        &account.keypair,
+                        Some(payload.clone()),
+                        Some(&account.url),
+                        Some(nonce),
+                        url,
+                    )
+                    .await
+                },
+                client,
+            )
             .await
             .map_err(|err| ErrorKind::FinalizeOrder.wrap(err))?;
-        if response.is_success() {
-            let order = response
-                .body_as_json::<Order>()
-                .await
-                .map_err(|err| ErrorKind::FinalizeOrder.wrap(err))?;
-            match order.status {
-                OrderStatus::Processing => Err(ErrorKind::OrderProcessing { csr }.into()),
-                OrderStatus::Valid { certificate } => {
-                    #[cfg(feature = "tracing")]
-                    debug!(download_url = certificate);
-                    Self::download_certificate(certificate, &csr, account, directory, client).await
-                }
-                _ => Err(ErrorKind::FinalizeOrder.into()),
-            }
-        } else {
-            #[cfg(feature = "tracing")]
-            if let Ok(text) = response.body_as_text().await {
-                debug!(body = ?text);
-            }
-            #[cfg(not(feature = "tracing"))]
-            let _ = response.body_as_text();
-            Err(ErrorKind::FinalizeOrder.into())
+        let retry_after = response.retry_after();
+        let order = response
+            .body_as_json::<Order>()
+            .await
+            .map_err(|err| ErrorKind::FinalizeOrder.wrap(err))?;
+        match order.status {
+            OrderStatus::Processing | OrderStatus::Valid { .. } => Ok(LocatedOrder {
+                pending: self.pending,
+                order,
+                retry_after,
+            }),
+            _ => Err(ErrorKind::FinalizeOrder.with_msg(format!("order is {}", order.status))),
         }
     }
     /// [RFC 8555 Downloading the Certificate](https://datatracker.ietf.org/doc/html/rfc8555#section-7.4.2)
+    /// The alternate chains are only downloaded if the default chain doesn't match
+    /// the preferred chain.
     #[cfg_attr(feature = "tracing", tracing::instrument(
         name = "download_certificate",
         skip_all,
@@ -459,47 +723,359 @@ impl LocatedOrder {
     async fn download_certificate<C: HttpClient<R>, R: Response>(
         url: impl AsRef<str>,
         csr: &Csr,
+        preferred_chain: Option<&PreferredChain>,
         account: &AccountMaterial,
         directory: &Directory,
         client: &C,
     ) -> Result<String> {
-        let url = url.as_ref();
-        let nonce = directory.new_nonce(client).await?;
-        let body = jose(
-            &account.keypair,
-            None,
-            Some(&account.url),
-            Some(&nonce),
-            url,
-        );
-        let response = client
-            .post_jose(&url, &body)
-            .await
-            .map_err(|err| ErrorKind::DownloadCertificate.wrap(err))?;
-        if response.is_success() {
-            let pem_certificate_chain = response
-                .body_as_text()
-                .await
+        let (mut pem_certificate_chain, alternates) =
+            Self::download_chain(url.as_ref(), account, directory, client).await?;
+        if let Some(preferred_chain) = preferred_chain {
+            let issuers = chain_issuers(&pem_certificate_chain)
                 .map_err(|err| ErrorKind::DownloadCertificate.wrap(err))?;
-            Ok([csr.private_key_pem.clone(), pem_certificate_chain].join("\n"))
-        } else {
             #[cfg(feature = "tracing")]
-            if let Ok(text) = response.body_as_text().await {
-                debug!(body = ?text);
+            debug!(default_chain = ?issuers, alternates = alternates.len());
+            if !preferred_chain.matches(&issuers) {
+                for url in alternates {
+                    let (alternate, _) =
+                        Self::download_chain(&url, account, directory, client).await?;
+                    let issuers = chain_issuers(&alternate)
+                        .map_err(|err| ErrorKind::DownloadCertificate.wrap(err))?;
+                    #[cfg(feature = "tracing")]
+                    debug!(alternate_chain = ?issuers);
+                    if preferred_chain.matches(&issuers) {
+                        pem_certificate_chain = alternate;
+                        break;
+                    }
+                }
             }
-            #[cfg(not(feature = "tracing"))]
-            let _ = response.body_as_text();
-            Err(ErrorKind::DownloadCertificate.into())
         }
+        Ok([csr.private_key_pem.clone(), pem_certificate_chain].join("\n"))
+    }
+    /// List the default chain and the alternate chains of a certificate, with their issuers.
+    #[cfg_attr(feature = "tracing", tracing::instrument(
+        name = "certificate_chains",
+        skip(account,directory,client),
+        level = tracing::Level::DEBUG,
+        err(level = tracing::Level::WARN)
+    ))]
+    pub(crate) async fn certificate_chains<C: HttpClient<R>, R: Response>(
+        url: &str,
+        account: &AccountMaterial,
+        directory: &Directory,
+        client: &C,
+    ) -> Result<Vec<CertificateChain>> {
+        let (pem_certificate_chain, alternates) =
+            Self::download_chain(url, account, directory, client).await?;
+        let mut chains = vec![CertificateChain {
+            url: url.to_string(),
+            issuers: chain_issuers(&pem_certificate_chain)
+                .map_err(|err| ErrorKind::DownloadCertificate.wrap(err))?,
+        }];
+        for url in alternates {
+            let (alternate, _) = Self::download_chain(&url, account, directory, client).await?;
+            chains.push(CertificateChain {
+                issuers: chain_issuers(&alternate)
+                    .map_err(|err| ErrorKind::DownloadCertificate.wrap(err))?,
+                url,
+            });
+        }
+        Ok(chains)
+    }
+    /// Download a certificate chain, and return it along with the urls of the alternate chains.
+    async fn download_chain<C: HttpClient<R>, R: Response>(
+        url: &str,
+        account: &AccountMaterial,
+        directory: &Directory,
+        client: &C,
+    ) -> Result<(String, Vec<String>)> {
+        let response = directory
+            .signed_request(
+                url,
+                async |nonce| {
+                    jose(&account.keypair, None, Some(&account.url), Some(nonce), url).await
+                },
+                client,
+            )
+            .await
+            .map_err(|err| ErrorKind::DownloadCertificate.wrap(err))?;
+        let alternates = response.links("alternate");
+        let pem_certificate_chain = response
+            .body_as_text()
+            .await
+            .map_err(|err| ErrorKind::DownloadCertificate.wrap(err))?;
+        Ok((pem_certificate_chain, alternates))
     }
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
+    use crate::mock::{MockClient, MockResponse, directory};
+    use rcgen::{CertificateParams, KeyPair};
     use rustls::crypto;
+    use serde_json::Value;
     use test_tracing::test;
 
+    const ACCOUNT_URL: &str = "https://example.com/acme/acct/evOfKhNU60wg";
+    const ORDER_URL: &str = "https://example.com/acme/order/TOlocE8rfgo";
+    const FINALIZE_URL: &str = "https://example.com/acme/order/TOlocE8rfgo/finalize";
+    const CERTIFICATE_URL: &str = "https://example.com/acme/cert/mAt3xBGaobw";
+
+    fn order_json(status: &str) -> Value {
+        let mut order = json!({
+            "status": status,
+            "identifiers": [{ "type": "dns", "value": "example.org" }],
+            "authorizations": ["https://example.com/acme/authz/PAniVnsZcis"],
+            "finalize": FINALIZE_URL
+        });
+        if status == "valid" {
+            order["certificate"] = json!(CERTIFICATE_URL);
+        }
+        order
+    }
+
+    fn located_order(status: &str, retry_after: Option<Duration>) -> LocatedOrder {
+        LocatedOrder {
+            pending: PendingOrder {
+                url: ORDER_URL.to_string(),
+                csr: vec!["example.org".to_string()].try_into().unwrap(),
+                preferred_chain: None,
+                deactivate_authorizations_on_failure: false,
+            },
+            order: serde_json::from_value(order_json(status)).unwrap(),
+            retry_after,
+        }
+    }
+
+    /// A certificate for the key of the CSR.
+    fn certificate_pem(csr: &Csr) -> String {
+        let key = KeyPair::from_pem(&csr.private_key_pem).unwrap();
+        CertificateParams::new(vec!["example.org".to_string()])
+            .unwrap()
+            .self_signed(&key)
+            .unwrap()
+            .pem()
+    }
+
+    #[test]
+    fn test_poll_delay() {
+        let now = Instant::now();
+        let deadline = now + Duration::from_secs(10);
+        assert_eq!(poll_delay(None, now, deadline), Some(DEFAULT_POLL_INTERVAL));
+        assert_eq!(
+            poll_delay(Some(Duration::ZERO), now, deadline),
+            Some(MIN_POLL_INTERVAL)
+        );
+        assert_eq!(
+            poll_delay(Some(Duration::from_secs(3600)), now, deadline),
+            Some(Duration::from_secs(10))
+        );
+        let deadline = now + Duration::from_millis(500);
+        assert_eq!(poll_delay(None, now, deadline), None);
+        assert_eq!(poll_delay(None, deadline, now), None);
+    }
+
+    #[test(tokio::test)]
+    async fn test_retry_after() {
+        for (value, expected) in [
+            ("120", Some(Duration::from_secs(120))),
+            (" 7 ", Some(Duration::from_secs(7))),
+            ("Wed, 21 Oct 2015 07:28:00 GMT", Some(Duration::ZERO)),
+            ("-1", None),
+        ] {
+            let response = MockResponse::new(200).with_header("retry-after", value);
+            assert_eq!(response.retry_after(), expected);
+        }
+        assert_eq!(MockResponse::new(200).retry_after(), None);
+        let client = MockClient::new().on(
+            ORDER_URL,
+            MockResponse::json(order_json("processing")).with_header("retry-after", "30"),
+        );
+        let account = AccountMaterial::mock(ACCOUNT_URL);
+        let (order, retry_after) = Order::fetch(ORDER_URL, &account, &directory(), &client)
+            .await
+            .unwrap();
+        assert_eq!(order.status, OrderStatus::Processing);
+        assert_eq!(retry_after, Some(Duration::from_secs(30)));
+    }
+
+    #[test(tokio::test)]
+    async fn test_process_polling() {
+        let located = located_order("ready", None);
+        let pem = certificate_pem(&located.pending.csr);
+        let client = MockClient::new()
+            .on(
+                FINALIZE_URL,
+                MockResponse::json(order_json("processing")).with_header("retry-after", "1"),
+            )
+            .on(ORDER_URL, MockResponse::json(order_json("valid")))
+            .on(
+                CERTIFICATE_URL,
+                MockResponse::new(200).with_body(pem.clone()),
+            );
+        let account = AccountMaterial::mock(ACCOUNT_URL);
+        let resolver = CertResolver::default();
+        let start = Instant::now();
+        let certificate = located
+            .process_steps(
+                start + ORDER_DEADLINE,
+                &account,
+                &directory(),
+                &resolver,
+                &client,
+            )
+            .await
+            .unwrap();
+        assert!(start.elapsed() >= Duration::from_secs(1));
+        assert!(certificate.ends_with(&pem));
+        assert_eq!(
+            client
+                .requests()
+                .into_iter()
+                .map(|(url, _)| url)
+                .collect::<Vec<_>>(),
+            vec![FINALIZE_URL, ORDER_URL, CERTIFICATE_URL]
+        );
+        let key = certified_key_from_pem(&certificate).unwrap();
+        assert_eq!(
+            resolver.map.pin().get("example.org").unwrap().key.cert,
+            key.cert
+        );
+    }
+
+    #[test(tokio::test)]
+    async fn test_process_deactivate_on_failure() {
+        let _ = crypto::ring::default_provider().install_default();
+        let authorization_url = "https://example.com/acme/authz/PAniVnsZcis";
+        let authorization = |status: &str| {
+            MockResponse::json(json!({
+                "status": status,
+                "identifier": { "type": "dns", "value": "example.org" },
+                "challenges": [{
+                    "type": "tls-alpn-01",
+                    "url": "https://example.com/acme/chall/PCt92wr-oA",
+                    "status": "pending",
+                    "token": "DGyRejmCefe7v4NfDGDKfA"
+                }]
+            }))
+        };
+        let account = AccountMaterial::mock(ACCOUNT_URL);
+        // The resolver doesn't manage example.org, so the challenge can't be accepted.
+        let resolver = CertResolver::default();
+        for deactivate in [false, true] {
+            let client = MockClient::new()
+                .on(authorization_url, authorization("pending"))
+                .on(authorization_url, authorization("pending"))
+                .on(authorization_url, authorization("deactivated"));
+            let mut located = located_order("pending", None);
+            located.pending.deactivate_authorizations_on_failure = deactivate;
+            let err = located
+                .process(&account, &directory(), &resolver, &client)
+                .await
+                .unwrap_err();
+            assert!(matches!(err.kind(), ErrorKind::Challenge));
+            let deactivations = client
+                .payloads(authorization_url)
+                .into_iter()
+                .filter(|it| it == &json!({ "status": "deactivated" }))
+                .count();
+            assert_eq!(deactivations, if deactivate { 1 } else { 0 });
+        }
+    }
+
+    #[test(tokio::test)]
+    async fn test_certificate_chains() {
+        let self_signed = |common_name: &str| {
+            let mut params = CertificateParams::new(vec!["example.org".to_string()]).unwrap();
+            params
+                .distinguished_name
+                .push(rcgen::DnType::CommonName, common_name);
+            params
+                .self_signed(&KeyPair::generate().unwrap())
+                .unwrap()
+                .pem()
+        };
+        let alternate_url = format!("{CERTIFICATE_URL}/1");
+        let client = MockClient::new()
+            .on(
+                CERTIFICATE_URL,
+                MockResponse::new(200)
+                    .with_header("link", format!(r#"<{alternate_url}>;rel="alternate""#))
+                    .with_header(
+                        "link",
+                        r#"<https://example.com/acme/directory>;rel="index""#,
+                    )
+                    .with_body(self_signed("Root A")),
+            )
+            .on(
+                &alternate_url,
+                MockResponse::new(200).with_body(self_signed("Root B")),
+            );
+        let account = AccountMaterial::mock(ACCOUNT_URL);
+        let chains =
+            LocatedOrder::certificate_chains(CERTIFICATE_URL, &account, &directory(), &client)
+                .await
+                .unwrap();
+        assert_eq!(
+            chains,
+            vec![
+                CertificateChain {
+                    url: CERTIFICATE_URL.to_string(),
+                    issuers: vec!["Root A".to_string()],
+                },
+                CertificateChain {
+                    url: alternate_url,
+                    issuers: vec!["Root B".to_string()],
+                }
+            ]
+        );
+        assert_eq!(chains[1].root(), Some("Root B"));
+        assert_eq!(chains[1].issuer(), Some("Root B"));
+    }
+
+    #[test(tokio::test)]
+    async fn test_process_deadline() {
+        // The Retry-After is past the deadline: the order is polled at the deadline,
+        // and the processing order is returned so that it can be resumed later.
+        let located = located_order("processing", Some(Duration::from_secs(3600)));
+        let client = MockClient::new().on(
+            ORDER_URL,
+            MockResponse::json(order_json("processing")).with_header("retry-after", "3600"),
+        );
+        let account = AccountMaterial::mock(ACCOUNT_URL);
+        let start = Instant::now();
+        let err = located
+            .process_steps(
+                start + Duration::from_millis(1500),
+                &account,
+                &directory(),
+                &CertResolver::default(),
+                &client,
+            )
+            .await
+            .unwrap_err();
+        assert!(start.elapsed() < Duration::from_secs(3));
+        assert!(
+            matches!(err.kind(), ErrorKind::OrderProcessing { order } if order.url() == ORDER_URL)
+        );
+        assert_eq!(client.payloads(ORDER_URL).len(), 1);
+        // Not enough time left for a single poll.
+        let located = located_order("processing", None);
+        let err = located
+            .process_steps(
+                Instant::now() + Duration::from_millis(500),
+                &account,
+                &directory(),
+                &CertResolver::default(),
+                &client,
+            )
+            .await
+            .unwrap_err();
+        assert!(matches!(err.kind(), ErrorKind::OrderProcessing { .. }));
+        assert_eq!(client.payloads(ORDER_URL).len(), 1);
+    }
+
     #[test]
     fn test_order_deserialization() {
         let json = serde_json::to_string_pretty(&json!({
@@ -547,7 +1123,311 @@ mod test {
         assert_eq!(
             deserialized.finalize,
             "https://example.com/acme/order/TOlocE8rfgo/finalize"
+        );
+        assert_eq!(
+            deserialized.expires,
+            OffsetDateTime::from_unix_timestamp(1453298947)
+                .ok()
+                .map(|it| it + time::Duration::milliseconds(990))
+        );
+        assert_eq!(
+            deserialized.not_before,
+            OffsetDateTime::from_unix_timestamp(1451606400).ok()
+        );
+        assert_eq!(
+            deserialized.not_after,
+            OffsetDateTime::from_unix_timestamp(1452211200).ok()
+        );
+    }
+
+    #[test]
+    fn test_order_options_validity() {
+        let not_before = OffsetDateTime::from_unix_timestamp(1451606400).unwrap();
+        let not_after = OffsetDateTime::from_unix_timestamp(1452211200).unwrap();
+        let order = serde_json::from_value::<Order>(json!({
+            "status": "pending",
+            "identifiers": [{ "type": "dns", "value": "example.org" }],
+            "notBefore": "2016-01-01T00:00:00Z",
+            "notAfter": "2016-01-08T00:00:00Z",
+            "authorizations": ["https://example.com/acme/authz/PAniVnsZcis"],
+            "finalize": "https://example.com/acme/order/TOlocE8rfgo/finalize"
+        }))
+        .unwrap();
+        let options = OrderOptions {
+            not_before: Some(not_before),
+            not_after: Some(not_after),
+            ..Default::default()
+        };
+        assert!(options.check_validity(&order).is_ok());
+        let options = OrderOptions {
+            not_before: Some(not_before + time::Duration::milliseconds(250)),
+            not_after: Some(not_after + time::Duration::milliseconds(999)),
+            ..Default::default()
+        };
+        assert!(options.check_validity(&order).is_ok());
+        let options = OrderOptions {
+            not_after: Some(not_after + time::Duration::DAY),
+            ..Default::default()
+        };
+        assert!(matches!(
+            options.check_validity(&order).unwrap_err().kind(),
+            ErrorKind::OrderValidity
+        ));
+        let order = Order {
+            not_after: None,
+            ..order
+        };
+        assert!(options.check_validity(&order).is_err());
+        assert!(OrderOptions::default().check_validity(&order).is_ok());
+        let options = OrderOptions {
+            not_before: Some(not_after),
+            not_after: Some(not_before),
+            ..Default::default()
+        };
+        let directory = serde_json::from_value::<Directory>(json!({
+            "newNonce": "https://example.com/acme/new-nonce",
+            "newAccount": "https://example.com/acme/new-account",
+            "newOrder": "https://example.com/acme/new-order",
+            "revokeCert": "https://example.com/acme/revoke-cert",
+            "keyChange": "https://example.com/acme/key-change"
+        }))
+        .unwrap();
+        assert!(options.validate(&directory).is_err());
+    }
+
+    #[test(tokio::test)]
+    async fn test_new_order_replaces() {
+        let client = MockClient::new().on(
+            "https://example.com/acme/new-order",
+            MockResponse::json(order_json("pending")).with_header("location", ORDER_URL),
+        );
+        let account = AccountMaterial::mock(ACCOUNT_URL);
+        let order = LocatedOrder::new_order(
+            ["example.org"].into_iter(),
+            Some("aYhba4dGQEHhs3uEe6CuLN4ByNQ.AIdlQyE"),
+            &OrderOptions::default(),
+            &account,
+            &directory(),
+            &client,
         )
+        .await
+        .unwrap();
+        assert_eq!(order.pending.url, ORDER_URL);
+        assert_eq!(
+            client.payloads("https://example.com/acme/new-order"),
+            vec![json!({
+                "identifiers": [{ "type": "dns", "value": "example.org" }],
+                "replaces": "aYhba4dGQEHhs3uEe6CuLN4ByNQ.AIdlQyE"
+            })]
+        );
+    }
+
+    #[test(tokio::test)]
+    async fn test_new_order_validity_mismatch() {
+        let _ = crypto::ring::default_provider().install_default();
+        let authorization_url = "https://example.com/acme/authz/PAniVnsZcis";
+        let authorization = |status: &str| {
+            MockResponse::json(json!({
+                "status": status,
+                "identifier": { "type": "dns", "value": "example.org" },
+                "challenges": []
+            }))
+        };
+        let mut order = order_json("pending");
+        order["notAfter"] = json!("2016-01-08T00:00:00Z");
+        let client = MockClient::new()
+            .on(
+                "https://example.com/acme/new-order",
+                MockResponse::json(order).with_header("location", ORDER_URL),
+            )
+            .on(authorization_url, authorization("pending"))
+            .on(authorization_url, authorization("deactivated"));
+        let account = AccountMaterial::mock(ACCOUNT_URL);
+        let options = OrderOptions {
+            not_before: Some(
+                OffsetDateTime::from_unix_timestamp(1451606400).unwrap()
+                    + time::Duration::milliseconds(500),
+            ),
+            not_after: Some(OffsetDateTime::from_unix_timestamp(1452816000).unwrap()),
+            ..Default::default()
+        };
+        let err = LocatedOrder::new_order(
+            ["example.org"].into_iter(),
+            None,
+            &options,
+            &account,
+            &directory(),
+            &client,
+        )
+        .await
+        .unwrap_err();
+        assert!(matches!(err.kind(), ErrorKind::OrderValidity));
+        let payload = &client.payloads("https://example.com/acme/new-order")[0];
+        assert_eq!(payload["notBefore"], json!("2016-01-01T00:00:00Z"));
+        assert_eq!(payload["notAfter"], json!("2016-01-15T00:00:00Z"));
+        // The pending authorization of the unusable order is deactivated.
+        assert_eq!(
+            client.payloads(authorization_url).last(),
+            Some(&json!({ "status": "deactivated" }))
+        );
+    }
+
+    #[test]
+    fn test_order_grouping() {
+        let domain_names: Vec<String> = (0..5).map(|i| format!("{i}.example.org")).collect();
+        assert_eq!(
+            OrderGrouping::All.groups(&domain_names),
+            vec![&domain_names[..]]
+        );
+        let groups = OrderGrouping::PerDomain.groups(&domain_names);
+        assert_eq!(groups.len(), 5);
+        assert!(groups.iter().all(|it| it.len() == 1));
+        let groups = OrderGrouping::Chunks(2).groups(&domain_names);
+        assert_eq!(
+            groups,
+            vec![&domain_names[0..2], &domain_names[2..4], &domain_names[4..]]
+        );
+        assert_eq!(OrderGrouping::Chunks(0).groups(&domain_names).len(), 5);
+    }
+
+    #[test(tokio::test)]
+    async fn test_list_orders() {
+        let _ = crypto::ring::default_provider().install_default();
+        let orders_url = "https://example.com/acme/orders/rzGoeA";
+        let next_url = "https://example.com/acme/orders/rzGoeA?cursor=2";
+        let other_order_url = "https://example.com/acme/order/4E16bbL5iSw";
+        let client = MockClient::new()
+            .on(
+                orders_url,
+                MockResponse::json(json!({ "orders": [ORDER_URL] }))
+                    .with_header("link", format!(r#"<{next_url}>;rel="next""#)),
+            )
+            // The last page links back to the first one.
+            .on(
+                next_url,
+                MockResponse::json(json!({ "orders": [other_order_url] }))
+                    .with_header("link", format!(r#"<{orders_url}>;rel="next""#)),
+            )
+            .on(ORDER_URL, MockResponse::json(order_json("pending")))
+            .on(other_order_url, MockResponse::json(order_json("invalid")));
+        let account = AccountMaterial::mock(ACCOUNT_URL);
+        let orders = list_orders(orders_url, &account, &directory(), &client)
+            .await
+            .unwrap();
+        assert_eq!(
+            orders
+                .iter()
+                .map(|it| (it.url.as_str(), it.status.clone()))
+                .collect::<Vec<_>>(),
+            vec![
+                (ORDER_URL, OrderStatus::Pending),
+                (other_order_url, OrderStatus::Invalid)
+            ]
+        );
+        assert_eq!(client.payloads(orders_url).len(), 1);
+        // One order that can't be fetched fails the listing.
+        let client = MockClient::new()
+            .on(
+                orders_url,
+                MockResponse::json(json!({ "orders": [ORDER_URL, other_order_url] })),
+            )
+            .on(ORDER_URL, MockResponse::json(order_json("pending")))
+            .on(
+                other_order_url,
+                MockResponse::problem(404, "malformed", "order not found"),
+            );
+        let err = list_orders(orders_url, &account, &directory(), &client)
+            .await
+            .unwrap_err();
+        assert!(matches!(err.kind(), ErrorKind::ListOrders));
+    }
+
+    #[test]
+    fn test_orders_list_deserialization() {
+        let list = serde_json::from_value::<OrdersList>(json!({
+            "orders": [
+                "https://example.com/acme/order/TOlocE8rfgo",
+                "https://example.com/acme/order/4E16bbL5iSw",
+                "https://example.com/acme/order/neBHYLfw0mg"
+            ]
+        }))
+        .unwrap();
+        assert_eq!(list.orders.len(), 3);
+        assert_eq!(list.orders[0], "https://example.com/acme/order/TOlocE8rfgo");
+    }
+
+    #[test]
+    fn test_pending_order_serialization() {
+        let order = PendingOrder {
+            url: "https://example.com/acme/order/TOlocE8rfgo".to_string(),
+            csr: vec!["example.org".to_string()].try_into().unwrap(),
+            preferred_chain: Some(PreferredChain::Root("ISRG Root X1".to_string())),
+            deactivate_authorizations_on_failure: true,
+        };
+        let json = order.to_json();
+        let deserialized = PendingOrder::from_json(&json).unwrap();
+        assert_eq!(deserialized.url(), order.url());
+        assert_eq!(deserialized.csr.der, order.csr.der);
+        assert_eq!(deserialized.csr.private_key_pem, order.csr.private_key_pem);
+        assert_eq!(deserialized.preferred_chain, order.preferred_chain);
+        assert!(deserialized.deactivate_authorizations_on_failure);
+        assert!(PendingOrder::from_json("{}").is_err());
+    }
+
+    #[test]
+    fn test_ip_identifier() {
+        let identifier = Identifier::from("192.0.2.1".to_string());
+        assert_eq!(identifier, Identifier::Ip("192.0.2.1".parse().unwrap()));
+        assert_eq!(
+            serde_json::to_value(&identifier).unwrap(),
+            json!({ "type": "ip", "value": "192.0.2.1" })
+        );
+        assert_eq!(identifier.to_string(), "192.0.2.1");
+        assert_eq!(identifier.server_name(), "1.2.0.192.in-addr.arpa");
+        let identifier =
+            serde_json::from_value::<Identifier>(json!({ "type": "ip", "value": "2001:db8::1" }))
+                .unwrap();
+        assert_eq!(identifier.to_string(), "2001:db8::1");
+        assert_eq!(
+            identifier.server_name(),
+            "1.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.8.b.d.0.1.0.0.2.ip6.arpa"
+        );
+        let identifier = Identifier::from("example.org".to_string());
+        assert_eq!(identifier, Identifier::Dns("example.org".to_string()));
+        assert_eq!(identifier.server_name(), "example.org");
+    }
+
+    #[test]
+    fn test_order_options_profile() {
+        let directory = serde_json::from_value::<Directory>(json!({
+            "newNonce": "https://example.com/acme/new-nonce",
+            "newAccount": "https://example.com/acme/new-account",
+            "newOrder": "https://example.com/acme/new-order",
+            "revokeCert": "https://example.com/acme/revoke-cert",
+            "keyChange": "https://example.com/acme/key-change",
+            "meta": {
+                "profiles": {
+                    "classic": "https://example.com/acme/profiles#classic",
+                    "shortlived": "https://example.com/acme/profiles#shortlived"
+                }
+            }
+        }))
+        .unwrap();
+        assert!(OrderOptions::default().validate(&directory).is_ok());
+        let options = OrderOptions {
+            profile: Some("shortlived".to_string()),
+            ..Default::default()
+        };
+        assert!(options.validate(&directory).is_ok());
+        let options = OrderOptions {
+            profile: Some("tlsserver".to_string()),
+            ..Default::default()
+        };
+        let err = options.validate(&directory).unwrap_err();
+        assert!(matches!(
+            err.kind(),
+            ErrorKind::UnsupportedProfile { profile } if profile == "tlsserver"
+        ));
     }
 
     #[cfg(feature = "reqwest")]
@@ -569,6 +1449,8 @@ mod test {
             Some("void.programingjd.me")
                 .iter()
                 .map(|&it| it.to_string()),
+            None,
+            &OrderOptions::default(),
             &account,
             &directory,
             &acme.client,
@@ -576,7 +1458,7 @@ mod test {
         .await
         .unwrap();
         #[cfg(feature = "tracing")]
-        tracing::trace!(order_url = order.url);
+        tracing::trace!(order_url = order.pending.url);
         assert_eq!(order.order.status, OrderStatus::Pending);
         assert_eq!(order.order.identifiers.len(), 1);
         assert_eq!(
@@ -0,0 +1,201 @@
+use crate::certificate::{ari_identifier, certificate_der};
+use crate::client::{HttpClient, Response};
+use crate::directory::Directory;
+use crate::errors::{ErrorKind, Problem, Result};
+use serde::Deserialize;
+use std::time::Duration;
+use time::OffsetDateTime;
+#[cfg(feature = "tracing")]
+use tracing::debug;
+
+/// [RFC 9773 Renewal Information](https://datatracker.ietf.org/doc/html/rfc9773#section-4.2)
+#[derive(Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct RenewalInfo {
+    #[serde(rename = "suggestedWindow")]
+    pub suggested_window: SuggestedWindow,
+    /// A page explaining the reason for the suggested window (a mass revocation event for instance).
+    #[serde(rename = "explanationURL")]
+    pub explanation_url: Option<String>,
+    /// The delay requested by the ACME server before checking the renewal info again.
+    #[serde(skip)]
+    pub retry_after: Option<Duration>,
+}
+
+/// [RFC 9773 Suggested Window](https://datatracker.ietf.org/doc/html/rfc9773#section-4.2)
+#[derive(Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct SuggestedWindow {
+    #[serde(with = "time::serde::rfc3339")]
+    pub start: OffsetDateTime,
+    #[serde(with = "time::serde::rfc3339")]
+    pub end: OffsetDateTime,
+}
+
+impl RenewalInfo {
+    /// Whether the suggested renewal window has started.
+    pub fn should_renew(&self) -> bool {
+        OffsetDateTime::now_utc() >= self.suggested_window.start
+    }
+}
+
+/// [RFC 9773 Getting Renewal Information](https://datatracker.ietf.org/doc/html/rfc9773#section-4)
+#[cfg_attr(feature = "tracing", tracing::instrument(
+    name = "get_renewal_info",
+    skip_all,
+    level = tracing::Level::DEBUG,
+    err(level = tracing::Level::WARN)
+))]
+pub(crate) async fn renewal_info<C: HttpClient<R>, R: Response>(
+    certificate: &[u8],
+    directory: &Directory,
+    client: &C,
+) -> Result<RenewalInfo> {
+    let url = directory
+        .renewal_info
+        .as_ref()
+        .ok_or(ErrorKind::RenewalInfo.with_msg("the acme server doesn't support renewal info"))?;
+    let certificate_id = certificate_der(certificate)
+        .and_then(|it| ari_identifier(&it))
+        .map_err(|err| ErrorKind::RenewalInfo.wrap(err))?;
+    let url = format!("{}/{certificate_id}", url.trim_end_matches('/'));
+    let response = client
+        .get_request(&url)
+        .await
+        .map_err(|err| ErrorKind::RenewalInfo.wrap(err))?;
+    if response.is_success() {
+        let retry_after = response.retry_after();
+        let renewal_info = response
+            .body_as_json::<RenewalInfo>()
+            .await
+            .map_err(|err| ErrorKind::RenewalInfo.wrap(err))?;
+        Ok(RenewalInfo {
+            retry_after,
+            ..renewal_info
+        })
+    } else {
+        let status = response.status_code();
+        let text = response
+            .body_as_text()
+            .await
+            .map_err(|err| ErrorKind::RenewalInfo.wrap(err))?;
+        #[cfg(feature = "tracing")]
+        debug!(body = ?text);
+        let err = match serde_json::from_str::<Problem>(&text) {
+            Ok(mut problem) => {
+                let _ = problem.status.get_or_insert(status);
+                ErrorKind::Rejected { status }.with_problem(problem)
+            }
+            Err(_) => ErrorKind::Rejected { status }.with_msg(text),
+        };
+        Err(ErrorKind::RenewalInfo.wrap(err))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::errors::ProblemType;
+    use crate::mock::{MockClient, MockResponse, directory};
+    use rcgen::{CertificateParams, KeyIdMethod, KeyPair, SerialNumber};
+    use serde_json::json;
+    use test_tracing::test;
+
+    /// The certificate of the RFC 9773 example, with the `aYhba4dGQEHhs3uEe6CuLN4ByNQ.AIdlQyE`
+    /// renewal info identifier.
+    fn certificate_pem() -> String {
+        let mut issuer_params =
+            CertificateParams::new(vec!["issuer.example.org".to_string()]).unwrap();
+        issuer_params.is_ca = rcgen::IsCa::Ca(rcgen::BasicConstraints::Unconstrained);
+        issuer_params.key_identifier_method = KeyIdMethod::PreSpecified(vec![
+            0x69, 0x88, 0x5B, 0x6B, 0x87, 0x46, 0x40, 0x41, 0xE1, 0xB3, 0x7B, 0x84, 0x7B, 0xA0,
+            0xAE, 0x2C, 0xDE, 0x01, 0xC8, 0xD4,
+        ]);
+        let issuer = rcgen::Issuer::new(issuer_params, KeyPair::generate().unwrap());
+        let mut params = CertificateParams::new(vec!["example.org".to_string()]).unwrap();
+        params.serial_number = Some(SerialNumber::from_slice(&[0x00, 0x87, 0x65, 0x43, 0x21]));
+        params.use_authority_key_identifier_extension = true;
+        params
+            .signed_by(&KeyPair::generate().unwrap(), &issuer)
+            .unwrap()
+            .pem()
+    }
+
+    #[test(tokio::test)]
+    async fn test_renewal_info() {
+        let url = "https://example.com/acme/renewal-info/aYhba4dGQEHhs3uEe6CuLN4ByNQ.AIdlQyE";
+        let client = MockClient::new().on(
+            url,
+            MockResponse::json(json!({
+                "suggestedWindow": {
+                    "start": "2025-01-02T04:00:00Z",
+                    "end": "2025-01-03T04:00:00Z"
+                },
+                "explanationURL": "https://acme.example.com/docs/ari"
+            }))
+            .with_header("retry-after", "21600"),
+        );
+        let renewal_info = renewal_info(certificate_pem().as_bytes(), &directory(), &client)
+            .await
+            .unwrap();
+        assert_eq!(
+            renewal_info,
+            RenewalInfo {
+                suggested_window: SuggestedWindow {
+                    start: OffsetDateTime::from_unix_timestamp(1735790400).unwrap(),
+                    end: OffsetDateTime::from_unix_timestamp(1735876800).unwrap(),
+                },
+                explanation_url: Some("https://acme.example.com/docs/ari".to_string()),
+                retry_after: Some(Duration::from_secs(21600)),
+            }
+        );
+        // The renewal info is not a signed request.
+        assert!(client.requests().is_empty());
+    }
+
+    #[test(tokio::test)]
+    async fn test_renewal_info_rejected() {
+        let url = "https://example.com/acme/renewal-info/aYhba4dGQEHhs3uEe6CuLN4ByNQ.AIdlQyE";
+        let client = MockClient::new().on(
+            url,
+            MockResponse::problem(404, "malformed", "certificate not found"),
+        );
+        let err = renewal_info(certificate_pem().as_bytes(), &directory(), &client)
+            .await
+            .unwrap_err();
+        assert!(matches!(err.kind(), ErrorKind::RenewalInfo));
+        let problem = err.problem().unwrap();
+        assert_eq!(problem.kind, ProblemType::Malformed);
+        assert_eq!(problem.status, Some(404));
+        let client = MockClient::new().on(url, MockResponse::new(500).with_body("unavailable"));
+        let err = renewal_info(certificate_pem().as_bytes(), &directory(), &client)
+            .await
+            .unwrap_err();
+        assert!(matches!(err.kind(), ErrorKind::RenewalInfo));
+        assert!(err.problem().is_none());
+    }
+
+    #[test]
+    fn test_renewal_info_deserialization() {
+        let json = serde_json::to_string_pretty(&json!({
+            "suggestedWindow": {
+                "start": "2025-01-02T04:00:00Z",
+                "end": "2025-01-03T04:00:00Z"
+            },
+            "explanationURL": "https://acme.example.com/docs/ari"
+        }))
+        .unwrap();
+        let deserialized = serde_json::from_str::<RenewalInfo>(json.as_str()).unwrap();
+        assert_eq!(
+            deserialized.suggested_window.start,
+            OffsetDateTime::from_unix_timestamp(1735790400).unwrap()
+        );
+        assert_eq!(
+            deserialized.suggested_window.end,
+            OffsetDateTime::from_unix_timestamp(1735876800).unwrap()
+        );
+        assert_eq!(
+            deserialized.explanation_url.as_deref(),
+            Some("https://acme.example.com/docs/ari")
+        );
+        assert!(deserialized.should_renew());
+    }
+}
@@ -1,8 +1,8 @@
 use crate::client::{HttpClient, Response};
-use crate::errors::{ErrorKind, Result};
+use crate::errors::{Error, ErrorKind, Problem, Result};
 use futures_timer::Delay;
 use reqwest::header::{HeaderMap, HeaderValue};
-use reqwest::Client;
+use reqwest::{Client, Method};
 use serde::de::DeserializeOwned;
 use serde_json::Value;
 use std::any::type_name;
@@ -11,88 +11,86 @@ use std::time::Duration;
 
 impl HttpClient<reqwest::Response> for Client {
     async fn get_request(&self, url: impl AsRef<str>) -> Result<reqwest::Response> {
-        let mut retry_count = 0;
-        loop {
-            match self.get(url.as_ref()).send().await {
-                Ok(response) => match response.status_code() {
-                    429 => return Err(ErrorKind::TooManyRequests.into()),
-                    503 | 504 => {
-                        let delay: u64 = match retry_count {
-                            0 => 5,
-                            1 => 30,
-                            2 => 120,
-                            3 => 600,
-                            _ => return Err(ErrorKind::ServiceUnavailable.into()),
-                        };
-                        retry_count += 1;
-                        Delay::new(Duration::from_secs(delay)).await;
-                    }
-                    _ => return Ok(response),
-                },
-                Err(_) => {
-                    let delay: u64 = match retry_count {
-                        0 => 1,
-                        1 => 5,
-                        2 => 30,
-                        3 => 120,
-                        _ => return Err(ErrorKind::ConnectionError.into()),
-                    };
-                    retry_count += 1;
-                    Delay::new(Duration::from_secs(delay)).await;
-                }
-            }
-        }
+        send(self, Method::GET, url.as_ref(), None).await
+    }
+    async fn head_request(&self, url: impl AsRef<str>) -> Result<reqwest::Response> {
+        send(self, Method::HEAD, url.as_ref(), None).await
     }
     async fn post_jose(
         &self,
         url: impl AsRef<str>,
         body: impl Borrow<Value>,
     ) -> Result<reqwest::Response> {
-        let mut headers = HeaderMap::new();
-        let _ = headers.insert(
-            "content-type",
-            HeaderValue::from_static("application/jose+json"),
-        );
-        let mut retry_count = 0;
-        loop {
-            match self
-                .post(url.as_ref())
-                .json(body.borrow())
-                .headers(headers.clone())
-                .send()
-                .await
-            {
-                Ok(response) => match response.status_code() {
-                    429 => return Err(ErrorKind::TooManyRequests.into()),
-                    503 | 504 => {
-                        let delay: u64 = match retry_count {
-                            0 => 5,
-                            1 => 30,
-                            2 => 120,
-                            3 => 600,
-                            _ => return Err(ErrorKind::ServiceUnavailable.into()),
-                        };
-                        retry_count += 1;
-                        Delay::new(Duration::from_secs(delay)).await;
-                    }
-                    _ => return Ok(response),
-                },
-                Err(_) => {
+        send(self, Method::POST, url.as_ref(), Some(body.borrow())).await
+    }
+}
+
+/// Send the request (with the jose body if there is one),
+/// and retry with increasing delays when the acme server can't be reached or is unavailable.
+/// Rate limited jose requests are returned as is, so that their nonce can be kept
+/// (see [crate::directory::Directory::signed_request]).
+async fn send(
+    client: &Client,
+    method: Method,
+    url: &str,
+    body: Option<&Value>,
+) -> Result<reqwest::Response> {
+    let mut headers = HeaderMap::new();
+    let _ = headers.insert(
+        "content-type",
+        HeaderValue::from_static("application/jose+json"),
+    );
+    let mut retry_count = 0;
+    loop {
+        let request = match body {
+            Some(body) => client
+                .request(method.clone(), url)
+                .json(body)
+                .headers(headers.clone()),
+            None => client.request(method.clone(), url),
+        };
+        match request.send().await {
+            Ok(response) => match response.status_code() {
+                429 if body.is_none() => return Err(too_many_requests(response).await),
+                503 | 504 => {
                     let delay: u64 = match retry_count {
-                        0 => 1,
-                        1 => 5,
-                        2 => 30,
-                        3 => 120,
-                        _ => return Err(ErrorKind::ConnectionError.into()),
+                        0 => 5,
+                        1 => 30,
+                        2 => 120,
+                        3 => 600,
+                        _ => return Err(ErrorKind::ServiceUnavailable.into()),
                     };
                     retry_count += 1;
                     Delay::new(Duration::from_secs(delay)).await;
                 }
+                _ => return Ok(response),
+            },
+            Err(_) => {
+                let delay: u64 = match retry_count {
+                    0 => 1,
+                    1 => 5,
+                    2 => 30,
+                    3 => 120,
+                    _ => return Err(ErrorKind::ConnectionError.into()),
+                };
+                retry_count += 1;
+                Delay::new(Duration::from_secs(delay)).await;
             }
         }
     }
 }
 
+/// Attach the rate limit problem document to the error.
+async fn too_many_requests(response: reqwest::Response) -> Error {
+    match response.json::<Problem>().await {
+        Ok(mut problem) => {
+            let _ = problem.status.get_or_insert(429);
+            ErrorKind::TooManyRequests.with_problem(problem)
+        }
+        Err(_) => ErrorKind::TooManyRequests.into(),
+    }
+}
+
 impl Response for reqwest::Response {
     fn status_code(&self) -> u16 {
         self.status().as_u16()
@@ -105,6 +103,13 @@ impl Response for reqwest::Response {
             .get(header_name.as_ref())
             .and_then(|it| it.to_str().map(|it| it.to_string()).ok())
     }
+    fn header_values(&self, header_name: impl AsRef<str>) -> Vec<String> {
+        self.headers()
+            .get_all(header_name.as_ref())
+            .iter()
+            .filter_map(|it| it.to_str().map(|it| it.to_string()).ok())
+            .collect()
+    }
     async fn body_as_json<T: DeserializeOwned>(self) -> Result<T> {
         self.json::<T>().await.map_err(|_| {
             ErrorKind::DeserializationError {
@@ -1,7 +1,10 @@
+use crate::errors::{ErrorKind, Result};
+use crate::order::Identifier;
 use flume::Sender;
-use papaya::HashMap;
+use papaya::{HashMap, HashSet};
 use rustls::crypto::ring::sign::any_supported_type;
-use rustls::pki_types::PrivateKeyDer;
+use rustls::pki_types::pem::PemObject;
+use rustls::pki_types::{CertificateDer, PrivateKeyDer};
 use rustls::server::{ClientHello, ResolvesServerCert};
 use rustls::sign::CertifiedKey;
 use std::fmt::Debug;
@@ -9,9 +12,15 @@ use std::sync::Arc;
 #[cfg(feature = "tracing")]
 use tracing::{debug, trace};
 
+/// Resolves the certificate to use for a TLS connection, by server name.
+/// Ip address identifiers are registered by their reverse dns name, which is the server name
+/// sent by the ACME server for [RFC 8738](https://datatracker.ietf.org/doc/html/rfc8738#section-6)
+/// tls-alpn-01 validations.
 #[derive(Debug, Default)]
 pub struct CertResolver {
     pub(crate) map: HashMap<String, DomainResolver>,
+    /// The server names of the ip address identifiers, for connections without a server name.
+    ip_names: HashSet<String>,
 }
 
 #[derive(Debug)]
@@ -31,6 +40,70 @@ impl From<CertifiedKey> for DomainResolver {
     }
 }
 
+impl CertResolver {
+    /// Replace the certificate for all the specified identifiers with the same certified key,
+    /// and clear any pending challenge state for those identifiers.
+    pub(crate) fn install<'a>(
+        &self,
+        identifiers: impl Iterator<Item = &'a Identifier>,
+        key: CertifiedKey,
+    ) {
+        let key = Arc::new(key);
+        let guard = self.map.pin();
+        for identifier in identifiers {
+            let server_name = identifier.server_name();
+            if matches!(identifier, Identifier::Ip(_)) {
+                self.ip_names.pin().insert(server_name.clone());
+            }
+            guard.insert(
+                server_name,
+                DomainResolver {
+                    key: key.clone(),
+                    challenge_key: None,
+                    notifier: None,
+                },
+            );
+        }
+    }
+    /// Clear any pending challenge state for the specified identifiers,
+    /// and keep their current certificate.
+    pub(crate) fn clear_challenges<'a>(&self, identifiers: impl Iterator<Item = &'a Identifier>) {
+        let guard = self.map.pin();
+        for identifier in identifiers {
+            // Atomic, so that a certificate installed concurrently is not replaced
+            // with the previous one.
+            let _ = guard.update(identifier.server_name(), |resolver| DomainResolver {
+                key: resolver.key.clone(),
+                challenge_key: None,
+                notifier: None,
+            });
+        }
+    }
+    /// Clients connecting to an ip address don't send a server name,
+    /// so we can only pick the certificate if there's a single ip address identifier.
+    fn ip_address_key(&self) -> Option<Arc<CertifiedKey>> {
+        let ip_names = self.ip_names.pin();
+        if ip_names.len() != 1 {
+            return None;
+        }
+        ip_names.iter().next().and_then(|name| {
+            self.map
+                .pin()
+                .get(name)
+                .map(|resolver| resolver.key.clone())
+        })
+    }
+    /// Stop serving certificates for the specified identifiers.
+    pub(crate) fn remove<'a>(&self, identifiers: impl Iterator<Item = &'a Identifier>) {
+        let guard = self.map.pin();
+        for identifier in identifiers {
+            let server_name = identifier.server_name();
+            let _ = self.ip_names.pin().remove(&server_name);
+            let _ = guard.remove(&server_name);
+        }
+    }
+}
+
 impl ResolvesServerCert for CertResolver {
     fn resolve(&self, client_hello: ClientHello) -> Option<Arc<CertifiedKey>> {
         #[cfg(feature = "tracing")]
@@ -49,13 +122,11 @@ impl ResolvesServerCert for CertResolver {
                     match &resolver.challenge_key {
                         Some(key) => {
                             if let Some(ref notifier) = resolver.notifier {
-                                let _ =
-                                    notifier
-                                        .try_send(server_name.to_string())
-                                        .inspect_err(|err| {
-                                            #[cfg(feature = "tracing")]
-                                            trace!("failed to notify resolver: {}", err);
-                                        });
+                                let _result = notifier.try_send(server_name.to_string());
+                                #[cfg(feature = "tracing")]
+                                if let Err(err) = _result {
+                                    trace!("failed to notify resolver: {}", err);
+                                }
                             }
                             Some(key.clone())
                         }
@@ -71,7 +142,7 @@ impl ResolvesServerCert for CertResolver {
                     .map(|resolver| resolver.key.clone())
             }
         } else {
-            None
+            self.ip_address_key()
         }
     }
 }
@@ -87,3 +158,144 @@ pub(crate) fn create_self_signed_certificate(domain_name: &str) -> CertifiedKey
         .expect("failed to generate signing key"),
     )
 }
+
+/// Create a certified key from a pem containing the private key and the certificate chain.
+pub fn certified_key_from_pem(pem: impl AsRef<[u8]>) -> Result<CertifiedKey> {
+    let pem = pem.as_ref();
+    let chain = CertificateDer::pem_slice_iter(pem)
+        .collect::<std::result::Result<Vec<_>, _>>()
+        .map_err(|_| ErrorKind::InstallCertificate.with_msg("invalid certificate chain"))?;
+    if chain.is_empty() {
+        return Err(ErrorKind::InstallCertificate.with_msg("missing certificate"));
+    }
+    let private_key = PrivateKeyDer::from_pem_slice(pem)
+        .map_err(|_| ErrorKind::InstallCertificate.with_msg("missing private key"))?;
+    let signing_key = any_supported_type(&private_key)
+        .map_err(|_| ErrorKind::InstallCertificate.with_msg("unsupported private key"))?;
+    let key = CertifiedKey::new(chain, signing_key);
+    key.keys_match()
+        .map_err(|_| ErrorKind::InstallCertificate.with_msg("private key does not match"))?;
+    Ok(key)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use test_tracing::test;
+
+    #[test]
+    fn test_install_certificate() {
+        let resolver = CertResolver::default();
+        let domain_names = vec![
+            "example.org".to_string(),
+            "www.example.org".to_string(),
+            "192.0.2.1".to_string(),
+        ];
+        let identifiers: Vec<Identifier> = domain_names
+            .iter()
+            .map(|it| Identifier::from(it.clone()))
+            .collect();
+        for (domain_name, identifier) in domain_names.iter().zip(&identifiers) {
+            resolver.map.pin().insert(
+                identifier.server_name(),
+                DomainResolver {
+                    key: Arc::new(create_self_signed_certificate(domain_name)),
+                    challenge_key: Some(Arc::new(create_self_signed_certificate(domain_name))),
+                    notifier: None,
+                },
+            );
+        }
+        let cert = rcgen::generate_simple_self_signed(domain_names.clone()).unwrap();
+        let pem = [cert.signing_key.serialize_pem(), cert.cert.pem()].join("\n");
+        let key = certified_key_from_pem(&pem).unwrap();
+        resolver.install(identifiers.iter(), key);
+        let guard = resolver.map.pin();
+        assert!(guard.get("1.2.0.192.in-addr.arpa").is_some());
+        for identifier in &identifiers {
+            let resolver = guard.get(&identifier.server_name()).unwrap();
+            assert!(resolver.challenge_key.is_none());
+            assert!(resolver.notifier.is_none());
+            assert_eq!(resolver.key.cert[0].as_ref(), cert.cert.der().as_ref());
+        }
+    }
+
+    #[test]
+    fn test_clear_challenges() {
+        let resolver = CertResolver::default();
+        let identifier = Identifier::from("example.org".to_string());
+        let key = Arc::new(create_self_signed_certificate("example.org"));
+        let (sender, _receiver) = flume::bounded(1);
+        resolver.map.pin().insert(
+            identifier.server_name(),
+            DomainResolver {
+                key: key.clone(),
+                challenge_key: Some(Arc::new(create_self_signed_certificate("example.org"))),
+                notifier: Some(sender),
+            },
+        );
+        resolver.clear_challenges(Some(&identifier).into_iter());
+        resolver.clear_challenges(Some(&Identifier::from("example.com".to_string())).into_iter());
+        let guard = resolver.map.pin();
+        let domain_resolver = guard.get("example.org").unwrap();
+        assert!(domain_resolver.challenge_key.is_none());
+        assert!(domain_resolver.notifier.is_none());
+        assert!(Arc::ptr_eq(&domain_resolver.key, &key));
+        assert!(guard.get("example.com").is_none());
+    }
+
+    #[test]
+    fn test_remove() {
+        let resolver = CertResolver::default();
+        let domain_names = vec!["example.org".to_string(), "www.example.org".to_string()];
+        let identifiers: Vec<_> = domain_names.into_iter().map(Identifier::from).collect();
+        for identifier in &identifiers {
+            resolver.map.pin().insert(
+                identifier.server_name(),
+                create_self_signed_certificate(&identifier.to_string()).into(),
+            );
+        }
+        resolver.remove(identifiers[..1].iter());
+        let guard = resolver.map.pin();
+        assert!(guard.get("example.org").is_none());
+        assert!(guard.get("www.example.org").is_some());
+    }
+
+    #[test]
+    fn test_ip_address_key() {
+        let resolver = CertResolver::default();
+        let identifiers: Vec<_> = ["example.org", "192.0.2.1", "2001:db8::1"]
+            .into_iter()
+            .map(|it| Identifier::from(it.to_string()))
+            .collect();
+        resolver.install(
+            identifiers[..2].iter(),
+            create_self_signed_certificate("192.0.2.1"),
+        );
+        assert!(resolver.ip_address_key().is_some());
+        resolver.install(
+            identifiers[2..].iter(),
+            create_self_signed_certificate("2001:db8::1"),
+        );
+        assert!(resolver.ip_address_key().is_none());
+        resolver.remove(identifiers[1..2].iter());
+        let key = resolver.ip_address_key().unwrap();
+        assert!(Arc::ptr_eq(
+            &key,
+            &resolver
+                .map
+                .pin()
+                .get(&identifiers[2].server_name())
+                .unwrap()
+                .key
+        ));
+        resolver.remove(identifiers[2..].iter());
+        assert!(resolver.ip_address_key().is_none());
+    }
+
+    #[test]
+    fn test_certified_key_from_invalid_pem() {
+        let cert = rcgen::generate_simple_self_signed(vec!["example.org".to_string()]).unwrap();
+        assert!(certified_key_from_pem(cert.cert.pem()).is_err());
+        assert!(certified_key_from_pem(cert.signing_key.serialize_pem()).is_err());
+    }
+}
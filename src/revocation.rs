@@ -0,0 +1,113 @@
+use crate::account::AccountMaterial;
+use crate::certificate::certificate_der;
+use crate::client::{HttpClient, Response};
+use crate::directory::Directory;
+use crate::errors::{ErrorKind, Result};
+use crate::jose::jose;
+use crate::key::AccountKey;
+use base64::Engine;
+use base64::prelude::BASE64_URL_SAFE_NO_PAD;
+use serde_json::json;
+
+/// [RFC 5280 Reason Code](https://datatracker.ietf.org/doc/html/rfc5280#section-5.3.1)
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum RevocationReason {
+    #[default]
+    Unspecified,
+    KeyCompromise,
+    CaCompromise,
+    AffiliationChanged,
+    Superseded,
+    CessationOfOperation,
+    CertificateHold,
+    RemoveFromCrl,
+    PrivilegeWithdrawn,
+    AaCompromise,
+}
+
+impl RevocationReason {
+    pub fn code(&self) -> u8 {
+        match self {
+            RevocationReason::Unspecified => 0,
+            RevocationReason::KeyCompromise => 1,
+            RevocationReason::CaCompromise => 2,
+            RevocationReason::AffiliationChanged => 3,
+            RevocationReason::Superseded => 4,
+            RevocationReason::CessationOfOperation => 5,
+            RevocationReason::CertificateHold => 6,
+            // 7 is not used
+            RevocationReason::RemoveFromCrl => 8,
+            RevocationReason::PrivilegeWithdrawn => 9,
+            RevocationReason::AaCompromise => 10,
+        }
+    }
+}
+
+/// The key used to sign the revocation request.
+pub(crate) enum RevocationKey<'a> {
+    /// The account that requested the certificate (or that is authorized for all its identifiers).
+    Account(&'a AccountMaterial),
+    /// The private key of the certificate.
+    Certificate(&'a AccountKey),
+}
+
+/// [RFC 8555 Certificate Revocation](https://datatracker.ietf.org/doc/html/rfc8555#section-7.6)
+#[cfg_attr(feature = "tracing", tracing::instrument(
+    name = "revoke_certificate",
+    skip_all,
+    level = tracing::Level::DEBUG,
+    err(level = tracing::Level::WARN)
+))]
+pub(crate) async fn revoke<C: HttpClient<R>, R: Response>(
+    certificate: &[u8],
+    reason: RevocationReason,
+    key: RevocationKey<'_>,
+    directory: &Directory,
+    client: &C,
+) -> Result<()> {
+    let certificate =
+        certificate_der(certificate).map_err(|err| ErrorKind::RevokeCertificate.wrap(err))?;
+    let url = &directory.revoke_cert;
+    let payload = json!({
+        "certificate": BASE64_URL_SAFE_NO_PAD.encode(certificate.as_ref()),
+        "reason": reason.code()
+    });
+    let response = directory
+        .signed_request(
+            url,
+            async |nonce| match key {
+                RevocationKey::Account(account) => {
+                    jose(
+                        &account.keypair,
+                        Some(payload.clone()),
+                        Some(&account.url),
+                        Some(nonce),
+                        url,
+                    )
+                    .await
+                }
+                RevocationKey::Certificate(keypair) => {
+                    jose(keypair, Some(payload.clone()), None, Some(nonce), url).await
+                }
+            },
+            client,
+        )
+        .await
+        .map_err(|err| ErrorKind::RevokeCertificate.wrap(err))?;
+    let _ = response.body_as_bytes().await;
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use test_tracing::test;
+
+    #[test]
+    fn test_reason_code() {
+        assert_eq!(RevocationReason::default().code(), 0);
+        assert_eq!(RevocationReason::KeyCompromise.code(), 1);
+        assert_eq!(RevocationReason::RemoveFromCrl.code(), 8);
+        assert_eq!(RevocationReason::AaCompromise.code(), 10);
+    }
+}